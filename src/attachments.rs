@@ -0,0 +1,116 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context};
+use serde::Serialize;
+
+use crate::fs::get_relative_path;
+use crate::links;
+
+/// Directory that uploaded files are stored in, inside a category or the notes directory
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// URL prefix that files in the notes directory are served under
+pub const ASSETS_URL: &str = "/api/assets/";
+
+/// A stored upload
+#[derive(Debug, Serialize)]
+pub struct Attachment {
+    /// Path of the file relative to the base directory
+    pub path: String,
+    /// Where the file is served
+    pub url: String,
+    /// Markdown that shows (for images) or links to the file from the note
+    pub markdown: String,
+}
+
+/// Store an uploaded file in the `attachments` directory next to a note.
+///
+/// `note_dir` is the directory of the note (or category) the file belongs
+/// to, relative to the base directory. The file name is cleaned up and made
+/// unique, so existing attachments are never overwritten.
+pub fn save_attachment(base_dir: &Path, note_dir: &Path, file_name: &str, data: &[u8]) -> Result<Attachment> {
+    let dir = base_dir.join(note_dir).join(ATTACHMENTS_DIR);
+    fs::create_dir_all(&dir).context("Failed to create attachments directory")?;
+    
+    let (path, mut file) = create_unique_file(&dir, &sanitize_file_name(file_name))?;
+    if let Err(err) = file.write_all(data).and_then(|_| file.sync_all()) {
+        let _ = fs::remove_file(&path);
+        return Err(err).context("Failed to write attachment");
+    }
+    
+    let rel_path = get_relative_path(base_dir, &path)?;
+    let rel_path = rel_path.to_string_lossy().replace('\\', "/");
+    let link = links::percent_encode_path(&links::relative_path(note_dir, Path::new(&rel_path)));
+    
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let markdown = if mime_guess::from_path(&path).first().is_some_and(|mime| mime.type_() == mime_guess::mime::IMAGE) {
+        let alt = path.file_stem().and_then(|n| n.to_str()).unwrap_or_default();
+        format!("![{}]({})", alt, link)
+    } else {
+        format!("[{}]({})", name, link)
+    };
+    
+    Ok(Attachment {
+        url: asset_url(&rel_path),
+        path: rel_path,
+        markdown,
+    })
+}
+
+/// The URL a file in the notes directory is served at, given its path relative to the base directory
+pub fn asset_url(rel_path: &str) -> String {
+    format!("{}{}", ASSETS_URL, links::percent_encode_path(rel_path))
+}
+
+/// Make an uploaded file name safe to store: no directories, no hidden
+/// files, and only characters that work in markdown links on any platform
+fn sanitize_file_name(file_name: &str) -> String {
+    // Browsers may send a full path; only keep the last part
+    let file_name = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    
+    let mut clean = String::with_capacity(file_name.len());
+    for c in file_name.chars() {
+        if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+            clean.push(c);
+        } else if c.is_whitespace() && !clean.ends_with('-') {
+            clean.push('-');
+        }
+    }
+    let clean = clean.trim_start_matches(['.', '-']);
+    
+    let (stem, extension) = split_extension(clean);
+    let stem: String = if stem.is_empty() { "attachment".to_string() } else { stem.chars().take(100).collect() };
+    match extension {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => stem,
+    }
+}
+
+/// Create a new file, adding `-1`, `-2`, ... to the name until it doesn't clash with an existing one
+fn create_unique_file(dir: &Path, file_name: &str) -> Result<(PathBuf, File)> {
+    let (stem, extension) = split_extension(file_name);
+    
+    for attempt in 0..1000 {
+        let name = match (attempt, extension) {
+            (0, _) => file_name.to_string(),
+            (_, Some(extension)) => format!("{}-{}.{}", stem, attempt, extension),
+            (_, None) => format!("{}-{}", stem, attempt),
+        };
+        let path = dir.join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err).context("Failed to create attachment"),
+        }
+    }
+    
+    Err(anyhow::anyhow!("Failed to find a free name for {}", file_name))
+}
+
+fn split_extension(file_name: &str) -> (&str, Option<&str>) {
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() => (stem, Some(extension)),
+        _ => (file_name, None),
+    }
+}
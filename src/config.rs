@@ -15,6 +15,134 @@ pub struct AppConfig {
     pub max_file_size_mb: u64,
     /// Enable dark mode by default
     pub default_dark_mode: bool,
+    /// Version history of notes
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// Git integration, for notes directories inside a git repository
+    #[serde(default)]
+    pub git: GitConfig,
+    /// Deleted notes and categories
+    #[serde(default)]
+    pub trash: TrashConfig,
+    /// How `#tags` are recognised in notes
+    #[serde(default)]
+    pub tags: TagsConfig,
+    /// Which files in the notes directory are scanned for notes
+    #[serde(default)]
+    pub scan: ScanConfig,
+    /// Which file extensions are notes
+    #[serde(default)]
+    pub formats: FormatsConfig,
+}
+
+/// Settings for the per-note version history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Snapshot notes before they are changed or deleted
+    pub enabled: bool,
+    /// The number of revisions to keep per note, 0 keeps all of them
+    pub max_revisions: usize,
+    /// Remove revisions older than this many days, 0 keeps them forever
+    pub max_age_days: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_revisions: 50,
+            max_age_days: 90,
+        }
+    }
+}
+
+/// Settings for the trash that deleted notes and categories are moved to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// Permanently delete items after this many days in the trash, 0 keeps them forever
+    pub retention_days: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: 30,
+        }
+    }
+}
+
+/// Settings for `#tags` written in the text of notes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TagsConfig {
+    /// Characters allowed in tags besides letters and digits; `/` separates nested tags
+    pub characters: String,
+}
+
+impl Default for TagsConfig {
+    fn default() -> Self {
+        Self {
+            characters: "_-/".to_string(),
+        }
+    }
+}
+
+/// Settings for finding notes in the notes directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanConfig {
+    /// Only list notes matching one of these globs (e.g. `journal/**`); empty lists every note
+    pub include: Vec<String>,
+    /// Skip files and directories matching any of these globs (e.g. `node_modules` or `*.draft.md`)
+    pub exclude: Vec<String>,
+    /// Skip files and directories listed in `.gitignore` files
+    pub gitignore: bool,
+    /// Scan hidden files and directories, whose names start with a dot
+    pub hidden: bool,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            gitignore: true,
+            hidden: false,
+        }
+    }
+}
+
+/// Settings for the kinds of files that are notes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormatsConfig {
+    /// Extensions of note files, without the dot; new notes get the first one.
+    /// `txt` notes are plain text and `org` notes Org mode, anything else is markdown
+    pub extensions: Vec<String>,
+}
+
+impl Default for FormatsConfig {
+    fn default() -> Self {
+        Self {
+            extensions: vec!["md".to_string(), "markdown".to_string(), "mdx".to_string()],
+        }
+    }
+}
+
+/// Settings for notes directories kept in a git repository
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitConfig {
+    /// Commit every change made through the app
+    pub auto_commit: bool,
+    /// Author name for automatic commits, instead of the one from the git config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    /// Author email for automatic commits, instead of the one from the git config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_email: Option<String>,
 }
 
 impl Default for AppConfig {
@@ -25,6 +153,12 @@ impl Default for AppConfig {
             bind_address: "127.0.0.1".to_string(),
             max_file_size_mb: 10,
             default_dark_mode: false,
+            history: HistoryConfig::default(),
+            git: GitConfig::default(),
+            trash: TrashConfig::default(),
+            tags: TagsConfig::default(),
+            scan: ScanConfig::default(),
+            formats: FormatsConfig::default(),
         }
     }
 }
@@ -67,6 +201,11 @@ impl AppConfig {
     //         .context(format!("Failed to write config file: {:?}", config_path))
     // }
     
+    /// The maximum file size in bytes
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size_mb.saturating_mul(1024 * 1024)
+    }
+    
     /// Get the server address with port
     pub fn server_address(&self) -> String {
         format!("{}:{}", self.bind_address, self.port)
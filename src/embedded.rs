@@ -19,10 +19,9 @@ pub fn serve_embedded_file(path: &str) -> Pin<Box<dyn Future<Output = impl IntoR
             "index.html".to_string()
         } else {
             // Remove leading slash if present
-            if path_owned.starts_with('/') {
-                path_owned[1..].to_string()
-            } else {
-                path_owned
+            match path_owned.strip_prefix('/') {
+                Some(stripped) => stripped.to_string(),
+                None => path_owned,
             }
         };
 
@@ -98,7 +97,7 @@ pub fn verify_essential_files() -> Result<(), Vec<&'static str>> {
     
     let missing_files: Vec<&str> = essential_files
         .into_iter()
-        .filter(|file| StaticAssets::get(*file).is_none())
+        .filter(|file| StaticAssets::get(file).is_none())
         .collect();
     
     if missing_files.is_empty() {
@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use crate::config::FormatsConfig;
+
+/// How the content of a note is written, going by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteFormat {
+    /// Markdown, with properties in YAML frontmatter
+    Markdown,
+    /// Plain text, without properties
+    PlainText,
+    /// Org mode, with properties in `#+KEY: value` lines at the top
+    Org,
+}
+
+impl NoteFormat {
+    /// The format of a note: `txt` files are plain text, `org` files Org
+    /// mode and everything else markdown
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+            Some("txt") => NoteFormat::PlainText,
+            Some("org") => NoteFormat::Org,
+            _ => NoteFormat::Markdown,
+        }
+    }
+}
+
+/// Checks whether a path names a note: a file with one of the configured
+/// extensions, or a README without an extension
+pub fn is_note_file(path: &Path, config: &FormatsConfig) -> bool {
+    let file_name = path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("");
+    
+    // Special case for README files, which are markdown
+    if file_name.eq_ignore_ascii_case("readme") {
+        return true;
+    }
+    
+    has_note_extension(file_name, config)
+}
+
+/// Whether a file name ends with one of the configured extensions
+pub fn has_note_extension(name: &str, config: &FormatsConfig) -> bool {
+    Path::new(name).extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| config.extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(ext)))
+}
+
+/// The extension new notes get, unless their name says otherwise
+pub fn default_extension(config: &FormatsConfig) -> &str {
+    config.extensions.first()
+        .map(|ext| ext.trim_start_matches('.'))
+        .unwrap_or("md")
+}
+
+/// The file name for a note called `name`: as it is if it already has a
+/// note extension, otherwise with `extension` added
+pub fn note_file_name(name: &str, extension: &str, config: &FormatsConfig) -> String {
+    if has_note_extension(name, config) {
+        name.to_string()
+    } else {
+        format!("{}.{}", name, extension)
+    }
+}
+
+/// Whether a link destination names a note rather than some other file
+pub fn is_note_link(path: &str, config: &FormatsConfig) -> bool {
+    strip_note_extension(path, config).len() < path.len()
+}
+
+/// A file name or path without its note extension, if it has one
+pub fn strip_note_extension<'a>(name: &'a str, config: &FormatsConfig) -> &'a str {
+    match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() && has_note_extension(name, config) => stem,
+        _ => name,
+    }
+}
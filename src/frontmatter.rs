@@ -0,0 +1,264 @@
+use std::cmp::Ordering;
+use serde_yaml::{Mapping, Value};
+use thiserror::Error;
+
+use crate::format::NoteFormat;
+
+/// The properties at the top of a note, in the order they were written
+pub type Properties = Mapping;
+
+/// Returned when a note's frontmatter isn't a valid YAML mapping
+#[derive(Debug, Error)]
+pub enum FrontmatterError {
+    #[error("Invalid frontmatter: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Invalid frontmatter: expected key-value pairs")]
+    NotAMapping,
+    #[error("Plain text notes can't have properties")]
+    Unsupported,
+}
+
+/// Split a note into its raw frontmatter (if present) and the body after it.
+///
+/// In markdown, frontmatter must start on the first line with `---` and ends
+/// at the next line that is exactly `---` or `...`. In Org mode it is the run
+/// of `#+KEY: value` lines the note starts with. Plain text has none.
+pub fn split(content: &str, format: NoteFormat) -> (Option<&str>, &str) {
+    match format {
+        NoteFormat::Markdown => split_yaml(content),
+        NoteFormat::Org => split_org(content),
+        NoteFormat::PlainText => (None, content),
+    }
+}
+
+/// Parse the frontmatter of a note; notes without frontmatter have no properties.
+///
+/// Org mode keywords are read with lowercase keys, and `#+FILETAGS: :a:b:`
+/// becomes the `tags` list.
+pub fn parse(content: &str, format: NoteFormat) -> Result<Properties, FrontmatterError> {
+    match (split(content, format).0, format) {
+        (None, _) => Ok(Properties::new()),
+        (Some(keywords), NoteFormat::Org) => Ok(parse_org(keywords)),
+        (Some(yaml), _) => parse_yaml(yaml),
+    }
+}
+
+/// Replace the frontmatter of a note, keeping the body as it is.
+///
+/// Notes without frontmatter get one, unless there are no properties to add;
+/// removing every property removes the frontmatter.
+pub fn replace(content: &str, properties: &Properties, format: NoteFormat) -> Result<String, FrontmatterError> {
+    let (frontmatter, body) = split(content, format);
+    
+    if properties.is_empty() {
+        return Ok(match frontmatter {
+            Some(_) => body.trim_start_matches(['\n', '\r']).to_string(),
+            None => content.to_string(),
+        });
+    }
+    
+    let header = match format {
+        NoteFormat::Markdown => format!("---\n{}---\n", serde_yaml::to_string(properties)?),
+        NoteFormat::Org => org_keywords(properties),
+        NoteFormat::PlainText => return Err(FrontmatterError::Unsupported),
+    };
+    let mut updated = String::with_capacity(header.len() + body.len() + 1);
+    updated.push_str(&header);
+    if frontmatter.is_none() {
+        // Keep the frontmatter visually apart from the existing content
+        updated.push('\n');
+    }
+    updated.push_str(body);
+    Ok(updated)
+}
+
+/// The tags listed in the properties, either as a YAML list or as a string
+/// of comma or space separated tags
+pub fn tags(properties: &Properties) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    let mut add = |tag: &str| {
+        let tag = tag.trim().trim_start_matches('#');
+        if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    };
+    
+    match properties.get("tags") {
+        Some(Value::Sequence(values)) => {
+            for value in values {
+                if let Some(tag) = scalar_to_string(value) {
+                    add(&tag);
+                }
+            }
+        },
+        Some(value) => {
+            if let Some(text) = scalar_to_string(value) {
+                text.split([',', ' ']).for_each(&mut add);
+            }
+        },
+        None => {},
+    }
+    
+    tags
+}
+
+/// Set the `tags` property, keeping its position if it already exists and
+/// removing it if there are no tags left
+pub fn set_tags(properties: &mut Properties, tags: &[String]) {
+    if tags.is_empty() {
+        properties.shift_remove("tags");
+        return;
+    }
+    
+    let value = Value::Sequence(tags.iter().map(|tag| Value::String(tag.clone())).collect());
+    match properties.get_mut("tags") {
+        Some(existing) => *existing = value,
+        None => {
+            properties.insert(Value::String("tags".to_string()), value);
+        },
+    }
+}
+
+/// Apply changes to the properties: existing keys keep their position, new
+/// keys are added at the end, and keys set to null are removed
+pub fn update(properties: &mut Properties, changes: Properties) {
+    for (key, value) in changes {
+        if value.is_null() {
+            properties.shift_remove(&key);
+        } else {
+            properties.insert(key, value);
+        }
+    }
+}
+
+/// A property as text, if it is a string, number or boolean
+pub fn get_string(properties: &Properties, key: &str) -> Option<String> {
+    properties.get(key)
+        .and_then(scalar_to_string)
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// A property value as text: lists are joined with commas, and nested
+/// mappings are written out as YAML
+pub fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Sequence(values) => values.iter().map(to_text).collect::<Vec<_>>().join(", "),
+        Value::Mapping(_) => serde_yaml::to_string(value).unwrap_or_default().trim_end().to_string(),
+        Value::Tagged(tagged) => to_text(&tagged.value),
+        _ => scalar_to_string(value).unwrap_or_default(),
+    }
+}
+
+/// Parse a value typed by the user, such as `3`, `true` or `active`, the
+/// way it would be read from frontmatter
+pub fn parse_value(text: &str) -> Value {
+    match serde_yaml::from_str(text) {
+        Ok(Value::Sequence(_) | Value::Mapping(_) | Value::Tagged(_)) | Err(_) => Value::String(text.to_string()),
+        Ok(value) => value,
+    }
+}
+
+/// Order two property values: numerically if both are numbers, otherwise
+/// as text ignoring case. ISO dates such as `2026-05-01` sort by date.
+pub fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        _ => to_text(a).to_lowercase().cmp(&to_text(b).to_lowercase()),
+    }
+}
+
+fn split_yaml(content: &str) -> (Option<&str>, &str) {
+    let rest = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let Some(rest) = rest.strip_prefix("---\n").or_else(|| rest.strip_prefix("---\r\n")) else {
+        return (None, content);
+    };
+    
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        if trimmed == "---" || trimmed == "..." {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    
+    (None, content)
+}
+
+// Org keywords run from the first line for as long as lines look like `#+KEY: value`
+fn split_org(content: &str) -> (Option<&str>, &str) {
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if org_keyword(line).is_none() {
+            break;
+        }
+        offset += line.len();
+    }
+    
+    if offset == 0 {
+        return (None, content);
+    }
+    (Some(&content[..offset]), &content[offset..])
+}
+
+fn org_keyword(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.strip_prefix("#+")?.split_once(':')?;
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((key, value.trim()))
+}
+
+fn parse_org(keywords: &str) -> Properties {
+    let mut properties = Properties::new();
+    for (key, value) in keywords.lines().filter_map(org_keyword) {
+        let key = key.to_lowercase();
+        if key == "filetags" {
+            let tags = value.split([':', ' ']).filter(|tag| !tag.is_empty()).map(|tag| Value::String(tag.to_string()));
+            properties.insert("tags".into(), Value::Sequence(tags.collect()));
+        } else {
+            properties.insert(key.into(), parse_value(value));
+        }
+    }
+    properties
+}
+
+fn org_keywords(properties: &Properties) -> String {
+    let mut keywords = String::new();
+    for (key, value) in properties {
+        let key = to_text(key);
+        let line = if key == "tags" {
+            let tags: Vec<String> = value.as_sequence()
+                .map(|tags| tags.iter().map(to_text).collect())
+                .unwrap_or_else(|| vec![to_text(value)]);
+            format!("#+FILETAGS: :{}:\n", tags.join(":"))
+        } else {
+            format!("#+{}: {}\n", key.to_uppercase(), to_text(value).replace('\n', " "))
+        };
+        keywords.push_str(&line);
+    }
+    keywords
+}
+
+fn parse_yaml(yaml: &str) -> Result<Properties, FrontmatterError> {
+    if yaml.trim().is_empty() {
+        return Ok(Properties::new());
+    }
+    
+    match serde_yaml::from_str(yaml)? {
+        Value::Mapping(properties) => Ok(properties),
+        Value::Null => Ok(Properties::new()),
+        _ => Err(FrontmatterError::NotAMapping),
+    }
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
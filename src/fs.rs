@@ -1,110 +1,189 @@
-use std::path::{Path, PathBuf};
-use std::fs;
-use std::io;
-use walkdir::WalkDir;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 use anyhow::{Result, Context};
+use thiserror::Error;
+use sha2::{Digest, Sha256};
+
+use crate::config::{FormatsConfig, TagsConfig};
+use crate::format::{self, NoteFormat};
+use crate::frontmatter;
+use crate::tags::{self, TagChange, TagRename};
+use crate::trash::{Trash, TrashEntry};
+
+/// Directory inside the notes directory where mdlib keeps its own data
+pub const DATA_DIR: &str = ".mdlib";
 
 /// Represents a markdown file
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct MarkdownFile {
+    /// Path relative to the base directory
     pub path: PathBuf,
     pub name: String,
+    /// Title from the frontmatter or the first heading
+    pub title: Option<String>,
     pub modified: Option<u64>,
     pub size: u64,
     pub tags: Vec<String>,
     pub category: Option<String>,
+    /// The file is bigger than the configured limit, so its content wasn't read
+    pub too_large: bool,
+    /// Frontmatter properties, available to queries
+    #[serde(skip)]
+    pub properties: frontmatter::Properties,
+}
+
+/// Returned for files bigger than the configured `max_file_size_mb`
+#[derive(Debug, Error)]
+#[error("File is too large ({:.1} MB); the limit is {} MB", *.size as f64 / MEGABYTE as f64, .limit / MEGABYTE)]
+pub struct FileTooLarge {
+    pub size: u64,
+    pub limit: u64,
 }
 
-/// Lists all markdown files in the given directory and its subdirectories
-pub fn list_markdown_files(dir: &Path) -> Result<Vec<MarkdownFile>> {
-    let mut files = Vec::new();
+const MEGABYTE: u64 = 1024 * 1024;
+
+/// Errors returned when resolving a user-supplied path inside the notes directory
+#[derive(Debug, Error)]
+pub enum VaultPathError {
+    #[error("Invalid path: {0}")]
+    Invalid(&'static str),
+    #[error("Path is outside the notes directory")]
+    OutsideVault,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Resolves a user-supplied relative path against the base directory.
+///
+/// Absolute paths, `..` components that climb above the base directory and
+/// symlinks that point outside of it are rejected. The returned path does not
+/// have to exist yet, so it can be used as a target for new files.
+pub fn resolve_vault_path(base_dir: &Path, user_path: &str) -> Result<PathBuf, VaultPathError> {
+    let user_path = user_path.trim();
+    if user_path.is_empty() {
+        return Err(VaultPathError::Invalid("path cannot be empty"));
+    }
+    if user_path.contains('\0') {
+        return Err(VaultPathError::Invalid("path contains a NUL byte"));
+    }
     
-    for entry in WalkDir::new(dir)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
-        
-        // Skip directories, only process files
-        if !path.is_file() {
-            continue;
-        }
-        
-        // Special case for README files
-        let file_name = path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
-            
-        if file_name.eq_ignore_ascii_case("readme") || file_name.eq_ignore_ascii_case("readme.md") {
-            let metadata = fs::metadata(path).context("Failed to read file metadata")?;
-            let modified = metadata.modified()
-                .ok()
-                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH)
-                    .ok()
-                    .map(|d| d.as_secs()));
-            
-            // Extract category from the path (relative to base dir)
-            let category = get_category_from_path(dir, path);
-            
-            // Extract tags from file content
-            let tags = extract_tags_from_file(path).unwrap_or_default();
-            
-            files.push(MarkdownFile {
-                path: path.to_path_buf(),
-                name: file_name.to_string(),
-                modified,
-                size: metadata.len(),
-                tags,
-                category,
-            });
-            continue;
-        }
-        
-        // Check if it's a markdown file with .md extension
-        if let Some(ext) = path.extension() {
-            if ext == "md" {
-                let metadata = fs::metadata(path).context("Failed to read file metadata")?;
-                let modified = metadata.modified()
-                    .ok()
-                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH)
-                        .ok()
-                        .map(|d| d.as_secs()));
-                
-                let name = path.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("Untitled.md")
-                    .to_string();
-                
-                // Extract category from the path (relative to base dir)
-                let category = get_category_from_path(dir, path);
-                
-                // Extract tags from file content
-                let tags = extract_tags_from_file(path).unwrap_or_default();
-                
-                files.push(MarkdownFile {
-                    path: path.to_path_buf(),
-                    name,
-                    modified,
-                    size: metadata.len(),
-                    tags,
-                    category,
-                });
+    // Treat backslashes as separators on every platform
+    let normalized = user_path.replace('\\', "/");
+    
+    // Lexically clean the path, refusing to climb above the base directory
+    let mut clean = PathBuf::new();
+    for component in Path::new(&normalized).components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if !clean.pop() {
+                    return Err(VaultPathError::OutsideVault);
+                }
+            },
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(VaultPathError::Invalid("absolute paths are not allowed"));
             }
         }
     }
     
-    // Sort by name
-    files.sort_by(|a, b| a.name.cmp(&b.name));
+    if clean.as_os_str().is_empty() {
+        return Err(VaultPathError::Invalid("path must point inside the notes directory"));
+    }
+    if clean.starts_with(DATA_DIR) {
+        return Err(VaultPathError::Invalid("the .mdlib directory is reserved"));
+    }
+    
+    let canonical_base = base_dir.canonicalize()?;
+    let candidate = base_dir.join(&clean);
+    
+    // Resolve symlinks on the deepest part of the path that already exists
+    let existing = candidate.ancestors()
+        .find(|p| p.symlink_metadata().is_ok())
+        .unwrap_or(base_dir);
     
-    Ok(files)
+    let resolved = match existing.canonicalize() {
+        Ok(resolved) => resolved,
+        // A dangling symlink could point anywhere, so don't follow it
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(VaultPathError::OutsideVault),
+        Err(err) => return Err(err.into()),
+    };
+    
+    if !resolved.starts_with(&canonical_base) {
+        return Err(VaultPathError::OutsideVault);
+    }
+    
+    Ok(candidate)
 }
 
-/// Reads the content of a markdown file (or README)
-pub fn read_markdown_file(path: &Path) -> Result<String> {
+/// Reads the metadata, tags and title of a single markdown file, returning
+/// the content that was read along with it.
+///
+/// Files bigger than `max_size` bytes are not read; they are returned
+/// flagged as too large, without tags or title and with empty content.
+pub fn load_markdown_file(base_dir: &Path, path: &Path, max_size: u64, tags_config: &TagsConfig) -> Result<(MarkdownFile, String)> {
+    let metadata = fs::metadata(path).context("Failed to read file metadata")?;
+    let modified = metadata.modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs()));
+    
+    let name = path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("Untitled.md")
+        .to_string();
+    
+    // Extract category from the path (relative to base dir)
+    let category = get_category_from_path(base_dir, path);
+    
+    // Extract tags and title from file content
+    let too_large = metadata.len() > max_size;
+    let content = if too_large {
+        String::new()
+    } else {
+        fs::read_to_string(path).unwrap_or_default()
+    };
+    let format = NoteFormat::of(path);
+    let tags = extract_tags_from_content(&content, format, tags_config).unwrap_or_default();
+    let title = extract_title_from_content(&content, format);
+    let properties = frontmatter::parse(&content, format).unwrap_or_default();
+    
+    let file = MarkdownFile {
+        path: path.strip_prefix(base_dir).unwrap_or(path).to_path_buf(),
+        name,
+        title,
+        modified,
+        size: metadata.len(),
+        tags,
+        category,
+        too_large,
+        properties,
+    };
+    
+    Ok((file, content))
+}
+
+/// Reads the content of a markdown file (or README), refusing files bigger than `max_size` bytes
+pub fn read_markdown_file(path: &Path, max_size: u64) -> Result<String> {
+    let metadata = fs::metadata(path).context("Failed to read file")?;
+    check_file_size(metadata.len(), max_size)?;
     fs::read_to_string(path).context("Failed to read file")
 }
 
+/// Fails with `FileTooLarge` if `size` is more than `max_size` bytes
+pub fn check_file_size(size: u64, max_size: u64) -> Result<()> {
+    if size > max_size {
+        return Err(FileTooLarge { size, limit: max_size }.into());
+    }
+    Ok(())
+}
+
 /// Writes content to a markdown file
 pub fn write_markdown_file(path: &Path, content: &str) -> Result<()> {
     // Create parent directories if they don't exist
@@ -112,27 +191,163 @@ pub fn write_markdown_file(path: &Path, content: &str) -> Result<()> {
         fs::create_dir_all(parent).context("Failed to create parent directories")?;
     }
     
-    fs::write(path, content).context("Failed to write file")
+    write_atomic(path, content.as_bytes())
 }
 
-/// Creates a new markdown file with the given name
-pub fn create_markdown_file(dir: &Path, name: &str, content: &str) -> Result<PathBuf> {
-    let file_name = if name.ends_with(".md") {
-        name.to_string()
-    } else {
-        format!("{}.md", name)
+/// Replaces the content of a file so that it is never left half-written.
+///
+/// The content goes to a temporary file in the same directory first, which
+/// is flushed to disk and then renamed over the original. The original's
+/// permissions are kept, and symlinks are followed rather than replaced.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let path = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            fs::canonicalize(path).context("Failed to resolve symlink")?
+        },
+        _ => path.to_path_buf(),
+    };
+    let dir = path.parent().context("Invalid file path")?;
+    let permissions = fs::metadata(&path).ok().map(|metadata| metadata.permissions());
+    
+    // Hidden, so the file watcher and the index never see it
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("note");
+    let temp_path = dir.join(format!(
+        ".{}.{}-{}.tmp",
+        file_name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+    ));
+    
+    let written = (|| -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .context("Failed to create temporary file")?;
+        file.write_all(content).context("Failed to write file")?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions).context("Failed to set file permissions")?;
+        }
+        file.sync_all().context("Failed to flush file to disk")?;
+        fs::rename(&temp_path, &path).context("Failed to replace file")
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return written;
+    }
+    
+    // Make the rename itself durable; not possible (or needed) on every platform
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Runs a read-modify-write of a file while holding a lock for its path, so
+/// concurrent changes to the same file through the app can't overwrite each other
+pub fn with_file_lock<T>(path: &Path, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    static LOCKS: OnceLock<Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>> = OnceLock::new();
+    let locks = LOCKS.get_or_init(Default::default);
+    
+    let lock = locks.lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .entry(path.to_path_buf())
+        .or_default()
+        .clone();
+    
+    let result = {
+        let _guard = lock.lock().unwrap_or_else(|err| err.into_inner());
+        f()
     };
     
-    let path = dir.join(file_name);
+    // Drop the lock once nobody else is waiting for it
+    let mut locks = locks.lock().unwrap_or_else(|err| err.into_inner());
+    if Arc::strong_count(&lock) == 2 {
+        locks.remove(path);
+    }
+    result
+}
+
+/// Creates a new markdown file with the given name, adding the default
+/// note extension unless it already has a note extension
+pub fn create_markdown_file(dir: &Path, name: &str, content: &str, formats: &FormatsConfig) -> Result<PathBuf> {
+    let file_name = format::note_file_name(name, format::default_extension(formats), formats);
+    
+    let path = resolve_vault_path(dir, &file_name)?;
     
     write_markdown_file(&path, content)?;
     
     Ok(path)
 }
 
-/// Deletes a markdown file
-pub fn delete_markdown_file(path: &Path) -> Result<()> {
-    fs::remove_file(path).context("Failed to delete file")
+/// Computes a strong ETag for a note's content
+pub fn content_etag(content: &str) -> String {
+    let digest = Sha256::digest(content.as_bytes());
+    let hex: String = digest.iter().take(16).map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// The current time in milliseconds since the Unix epoch, as used for
+/// history revisions and trash entries
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Deletes a markdown file by moving it to the trash
+pub fn delete_markdown_file(path: &Path, trash: &Trash) -> Result<TrashEntry> {
+    trash.delete(path)
+}
+
+/// Works out where a note would be moved to, without touching the disk.
+///
+/// The target is given relative to the base directory and keeps the note's
+/// extension if it has no note extension of its own. Fails if another note
+/// already exists there.
+pub fn move_target(base_dir: &Path, source: &Path, new_path: &str, formats: &FormatsConfig) -> Result<PathBuf> {
+    let new_path = new_path.trim().trim_start_matches(['/', '\\']);
+    if new_path.is_empty() {
+        return Err(anyhow::anyhow!("Target path cannot be empty"));
+    }
+    
+    let extension = source.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_else(|| format::default_extension(formats));
+    let file_name = format::note_file_name(new_path, extension, formats);
+    
+    let target = resolve_vault_path(base_dir, &file_name)?;
+    
+    // Never overwrite an existing note, unless it's the note itself
+    if target != source && target.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "A note already exists at the target path").into());
+    }
+    
+    Ok(target)
+}
+
+/// Moves (or renames) a markdown file to a new location inside the base directory.
+///
+/// See `move_target` for how the target is interpreted; missing category
+/// directories are created. Returns the new path relative to the base directory.
+pub fn move_markdown_file(base_dir: &Path, source: &Path, new_path: &str, formats: &FormatsConfig) -> Result<PathBuf> {
+    let target = move_target(base_dir, source, new_path, formats)?;
+    
+    // Moving a note onto itself is a no-op
+    if target == source {
+        return get_relative_path(base_dir, &target);
+    }
+    
+    // Create the target category directory if it doesn't exist
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).context("Failed to create target directory")?;
+    }
+    
+    fs::rename(source, &target).context("Failed to move file")?;
+    
+    get_relative_path(base_dir, &target)
 }
 
 // /// Checks if a path exists
@@ -160,63 +375,59 @@ pub fn get_relative_path(base: &Path, path: &Path) -> Result<PathBuf> {
     }
 }
 
-/// Extract tags from a markdown file
-fn extract_tags_from_file(path: &Path) -> Result<Vec<String>> {
-    let content = fs::read_to_string(path).context("Failed to read file for tags")?;
-    extract_tags_from_content(&content)
-}
-
-/// Extract tags from a markdown content string
-pub fn extract_tags_from_content(content: &str) -> Result<Vec<String>> {
-    // Look for tags in the format #tag or tags: [tag1, tag2, tag3]
-    let mut tags = Vec::new();
-    
-    // First check for a YAML frontmatter section with tags
-    if let Some(frontmatter) = extract_frontmatter(content) {
-        // Look for a tags: line in the frontmatter
-        for line in frontmatter.lines() {
-            let line = line.trim();
-            if let Some(stripped) = line.strip_prefix("tags:") {
-                // Parse the tags list
-                let tags_part = stripped.trim();
-                if tags_part.starts_with('[') && tags_part.ends_with(']') {
-                    // Format: tags: [tag1, tag2, tag3]
-                    let tags_list = &tags_part[1..tags_part.len()-1];
-                    tags.extend(tags_list.split(',')
-                        .map(|s| s.trim().to_string())
-                        .filter(|s| !s.is_empty()));
-                } else {
-                    // Format: tags: tag1 tag2 tag3
-                    tags.extend(tags_part.split_whitespace()
-                        .map(|s| s.trim().to_string())
-                        .filter(|s| !s.is_empty()));
-                }
-            }
-        }
-    }
+/// Extract tags from a note's content: the frontmatter `tags` property plus
+/// any `#hashtags` in the body
+pub fn extract_tags_from_content(content: &str, format: NoteFormat, config: &TagsConfig) -> Result<Vec<String>> {
+    // Notes with broken frontmatter still get their hashtags listed
+    let mut tags = frontmatter::parse(content, format)
+        .map(|properties| frontmatter::tags(&properties))
+        .unwrap_or_default();
     
-    // Then scan for hashtags in the content
-    for word in content.split_whitespace() {
-        if word.starts_with('#') && word.len() > 1 {
-            // Extract just the tag part (without #)
-            let tag = word[1..].trim_end_matches(|c: char| !c.is_alphanumeric()).to_string();
-            if !tag.is_empty() && !tags.contains(&tag) {
-                tags.push(tag);
-            }
+    for tag in tags::extract_hashtags(content, format, config) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
         }
     }
     
     Ok(tags)
 }
 
-/// Extract YAML frontmatter from markdown content if present
-fn extract_frontmatter(content: &str) -> Option<String> {
-    let trimmed = content.trim_start();
-    if let Some(stripped) = trimmed.strip_prefix("---") {
-        if let Some(end_index) = stripped.find("---") {
-            return Some(stripped[..end_index].trim().to_string());
+/// Extract the title from a note's content: a frontmatter `title:` or the
+/// first level-one heading. Plain text notes have no title.
+pub fn extract_title_from_content(content: &str, format: NoteFormat) -> Option<String> {
+    let title = frontmatter::parse(content, format)
+        .ok()
+        .and_then(|properties| frontmatter::get_string(&properties, "title"));
+    if title.is_some() {
+        return title;
+    }
+    
+    let heading_prefix = match format {
+        NoteFormat::Markdown => "# ",
+        NoteFormat::Org => "* ",
+        NoteFormat::PlainText => return None,
+    };
+    
+    // Fall back to the first level-one heading outside of code blocks
+    let (_, body) = frontmatter::split(content, format);
+    let mut in_code_block = false;
+    for line in body.lines() {
+        let line = line.trim();
+        if line.starts_with("```") || line.starts_with("~~~") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            continue;
+        }
+        if let Some(heading) = line.strip_prefix(heading_prefix) {
+            let heading = heading.trim();
+            if !heading.is_empty() {
+                return Some(heading.to_string());
+            }
         }
     }
+    
     None
 }
 
@@ -234,7 +445,7 @@ fn get_category_from_path(base_dir: &Path, file_path: &Path) -> Option<String> {
 
 /// Creates a new category directory
 pub fn create_category(dir: &Path, category_name: &str) -> Result<PathBuf> {
-    let path = dir.join(category_name);
+    let path = resolve_vault_path(dir, category_name)?;
     
     // Log that we're creating the category
     println!("Creating category directory at: {:?}", path);
@@ -250,20 +461,96 @@ pub fn create_category(dir: &Path, category_name: &str) -> Result<PathBuf> {
     Ok(path)
 }
 
-/// Add tags to a markdown file
-pub fn add_tags_to_file(path: &Path, tags: &[String]) -> Result<()> {
-    let content = fs::read_to_string(path).context("Failed to read file")?;
-    let new_content = add_tags_to_content(&content, tags)?;
-    fs::write(path, new_content).context("Failed to write file")
+/// Where a category would be moved to, without moving it.
+///
+/// `new_path` is the full new path of the category, so a category can be moved
+/// under another one. Fails if the category doesn't exist, would end up inside
+/// itself or the target is taken. Returns the new absolute path.
+pub fn move_category_target(base_dir: &Path, category_name: &str, new_path: &str) -> Result<PathBuf> {
+    let source = resolve_vault_path(base_dir, category_name)?;
+    let target = resolve_vault_path(base_dir, new_path)?;
+    
+    if !source.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "Category directory doesn't exist").into());
+    }
+    if target.starts_with(&source) {
+        return Err(VaultPathError::Invalid("a category can't be moved into itself").into());
+    }
+    if target.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "A category already exists at the target path").into());
+    }
+    
+    Ok(target)
 }
 
-/// Add tags to markdown content
-pub fn add_tags_to_content(content: &str, tags: &[String]) -> Result<String> {
+/// Moves (or renames) a category directory, along with everything in it.
+///
+/// See `move_category_target` for how the target is interpreted; missing
+/// parent categories are created. Returns the new absolute path.
+pub fn move_category(base_dir: &Path, category_name: &str, new_path: &str) -> Result<PathBuf> {
+    let source = resolve_vault_path(base_dir, category_name)?;
+    let target = move_category_target(base_dir, category_name, new_path)?;
+    
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).context("Failed to create parent category directory")?;
+    }
+    fs::rename(&source, &target).context("Failed to move category")?;
+    
+    Ok(target)
+}
+
+/// Point the `category` frontmatter of a note at a moved category.
+///
+/// Notes whose `category` is `from`, or a category inside it, get the same
+/// category under `to`. Returns whether the note changed, or with `dry_run`
+/// whether it would, leaving the file as it is. `before_write` runs under the
+/// file's lock right before it is overwritten, e.g. to snapshot it.
+pub fn update_category_in_file(path: &Path, from: &str, to: &str, max_size: u64, dry_run: bool, before_write: impl FnOnce() -> Result<()>) -> Result<bool> {
+    with_file_lock(path, || {
+        let content = read_markdown_file(path, max_size)?;
+        let format = NoteFormat::of(path);
+        let mut properties = frontmatter::parse(&content, format)?;
+        let Some(category) = frontmatter::get_string(&properties, "category") else {
+            return Ok(false);
+        };
+        if !tags::is_within(&category, from) {
+            return Ok(false);
+        }
+        
+        // Keep whatever was nested inside the moved category
+        let depth = from.trim_end_matches('/').split('/').count();
+        let nested: Vec<&str> = category.split('/').skip(depth).collect();
+        let category = if nested.is_empty() { to.to_string() } else { format!("{}/{}", to, nested.join("/")) };
+        properties.insert("category".into(), category.into());
+        
+        if !dry_run {
+            let new_content = frontmatter::replace(&content, &properties, format)?;
+            before_write()?;
+            write_atomic(path, new_content.as_bytes())?;
+        }
+        Ok(true)
+    })
+}
+
+/// Add tags to a markdown file, running `before_write` under the file's lock
+/// right before it is overwritten
+pub fn add_tags_to_file(path: &Path, tags: &[String], max_size: u64, before_write: impl FnOnce() -> Result<()>) -> Result<()> {
+    with_file_lock(path, || {
+        let content = read_markdown_file(path, max_size)?;
+        let new_content = add_tags_to_content(&content, NoteFormat::of(path), tags)?;
+        before_write()?;
+        write_atomic(path, new_content.as_bytes())
+    })
+}
+
+/// Add tags to the frontmatter of a note's content, keeping all other properties
+pub fn add_tags_to_content(content: &str, format: NoteFormat, tags: &[String]) -> Result<String> {
     if tags.is_empty() {
         return Ok(content.to_string());
     }
     
-    let mut existing_tags = extract_tags_from_content(content)?;
+    let mut properties = frontmatter::parse(content, format)?;
+    let mut existing_tags = frontmatter::tags(&properties);
     let mut added_any = false;
     
     // Add new tags if they don't already exist
@@ -279,64 +566,46 @@ pub fn add_tags_to_content(content: &str, tags: &[String]) -> Result<String> {
         return Ok(content.to_string());
     }
     
-    // Check if there's frontmatter
-    if let Some(frontmatter) = extract_frontmatter(content) {
-        // Update the frontmatter with the new tags
-        let mut updated_frontmatter = String::new();
-        let mut has_tags_line = false;
-        
-        for line in frontmatter.lines() {
-            if line.trim().starts_with("tags:") {
-                // Replace the tags line
-                updated_frontmatter.push_str(&format!("tags: [{}]\n", existing_tags.join(", ")));
-                has_tags_line = true;
-            } else {
-                updated_frontmatter.push_str(line);
-                updated_frontmatter.push('\n');
-            }
-        }
-        
-        if !has_tags_line {
-            // Add a new tags line
-            updated_frontmatter.push_str(&format!("tags: [{}]\n", existing_tags.join(", ")));
+    frontmatter::set_tags(&mut properties, &existing_tags);
+    Ok(frontmatter::replace(content, &properties, format)?)
+}
+
+/// Rename tags in a markdown file, returning what changed. With `dry_run`
+/// the file is left as it is; otherwise `before_write` runs under the file's
+/// lock right before it is overwritten.
+pub fn rename_tags_in_file(path: &Path, rename: &TagRename, tags_config: &TagsConfig, max_size: u64, dry_run: bool, before_write: impl FnOnce() -> Result<()>) -> Result<Vec<TagChange>> {
+    with_file_lock(path, || {
+        let content = read_markdown_file(path, max_size)?;
+        let Some((new_content, changes)) = tags::rename_tags(&content, NoteFormat::of(path), rename, tags_config)? else {
+            return Ok(Vec::new());
+        };
+        if !dry_run {
+            before_write()?;
+            write_atomic(path, new_content.as_bytes())?;
         }
-        
-        // Replace the frontmatter in the content
-        let start_idx = content.find("---").unwrap_or(0);
-        let end_idx = content[start_idx + 3..].find("---").map(|i| start_idx + i + 6).unwrap_or(start_idx);
-        let mut new_content = String::new();
-        new_content.push_str("---\n");
-        new_content.push_str(&updated_frontmatter);
-        new_content.push_str("---\n");
-        new_content.push_str(&content[end_idx..]);
-        
-        Ok(new_content)
-    } else {
-        // No frontmatter, add one
-        let mut new_content = String::new();
-        new_content.push_str("---\n");
-        new_content.push_str(&format!("tags: [{}]\n", existing_tags.join(", ")));
-        new_content.push_str("---\n\n");
-        new_content.push_str(content);
-        
-        Ok(new_content)
-    }
+        Ok(changes)
+    })
 }
 
-/// Remove tags from a markdown file
-pub fn remove_tags_from_file(path: &Path, tags_to_remove: &[String]) -> Result<()> {
-    let content = fs::read_to_string(path).context("Failed to read file")?;
-    let new_content = remove_tags_from_content(&content, tags_to_remove)?;
-    fs::write(path, new_content).context("Failed to write file")
+/// Remove tags from a markdown file, running `before_write` under the file's
+/// lock right before it is overwritten
+pub fn remove_tags_from_file(path: &Path, tags_to_remove: &[String], max_size: u64, before_write: impl FnOnce() -> Result<()>) -> Result<()> {
+    with_file_lock(path, || {
+        let content = read_markdown_file(path, max_size)?;
+        let new_content = remove_tags_from_content(&content, NoteFormat::of(path), tags_to_remove)?;
+        before_write()?;
+        write_atomic(path, new_content.as_bytes())
+    })
 }
 
-/// Remove tags from markdown content
-pub fn remove_tags_from_content(content: &str, tags_to_remove: &[String]) -> Result<String> {
+/// Remove tags from the frontmatter of a note's content, keeping all other properties
+pub fn remove_tags_from_content(content: &str, format: NoteFormat, tags_to_remove: &[String]) -> Result<String> {
     if tags_to_remove.is_empty() {
         return Ok(content.to_string());
     }
     
-    let mut existing_tags = extract_tags_from_content(content)?;
+    let mut properties = frontmatter::parse(content, format)?;
+    let mut existing_tags = frontmatter::tags(&properties);
     let initial_count = existing_tags.len();
     
     // Remove specified tags
@@ -347,62 +616,38 @@ pub fn remove_tags_from_content(content: &str, tags_to_remove: &[String]) -> Res
         return Ok(content.to_string());
     }
     
-    // Check if there's frontmatter
-    if let Some(frontmatter) = extract_frontmatter(content) {
-        // Update the frontmatter with the remaining tags
-        let mut updated_frontmatter = String::new();
-        let mut has_tags_line = false;
-        
-        for line in frontmatter.lines() {
-            if line.trim().starts_with("tags:") {
-                // Replace the tags line, or remove it if no tags left
-                if !existing_tags.is_empty() {
-                    updated_frontmatter.push_str(&format!("tags: [{}]\n", existing_tags.join(", ")));
-                }
-                has_tags_line = true;
-            } else {
-                updated_frontmatter.push_str(line);
-                updated_frontmatter.push('\n');
-            }
-        }
+    frontmatter::set_tags(&mut properties, &existing_tags);
+    Ok(frontmatter::replace(content, &properties, format)?)
+}
+
+/// Update the frontmatter properties of a markdown file, returning the result.
+/// `before_write` runs under the file's lock right before it is overwritten.
+pub fn update_properties_of_file(path: &Path, changes: frontmatter::Properties, max_size: u64, before_write: impl FnOnce() -> Result<()>) -> Result<frontmatter::Properties> {
+    with_file_lock(path, || {
+        let content = read_markdown_file(path, max_size)?;
+        let format = NoteFormat::of(path);
+        let mut properties = frontmatter::parse(&content, format)?;
+        frontmatter::update(&mut properties, changes);
         
-        // If there were no tags line but we have tags, add it
-        if !has_tags_line && !existing_tags.is_empty() {
-            updated_frontmatter.push_str(&format!("tags: [{}]\n", existing_tags.join(", ")));
+        let new_content = frontmatter::replace(&content, &properties, format)?;
+        if new_content != content {
+            before_write()?;
+            write_atomic(path, new_content.as_bytes())?;
         }
-        
-        // Replace the frontmatter in the content
-        let start_idx = content.find("---").unwrap_or(0);
-        let end_idx = content[start_idx + 3..].find("---").map(|i| start_idx + i + 6).unwrap_or(start_idx);
-        let mut new_content = String::new();
-        new_content.push_str("---\n");
-        new_content.push_str(&updated_frontmatter);
-        new_content.push_str("---\n");
-        new_content.push_str(&content[end_idx..]);
-        
-        Ok(new_content)
-    } else if !existing_tags.is_empty() {
-        // No frontmatter but we have tags, add a new frontmatter
-        let mut new_content = String::new();
-        new_content.push_str("---\n");
-        new_content.push_str(&format!("tags: [{}]\n", existing_tags.join(", ")));
-        new_content.push_str("---\n\n");
-        new_content.push_str(content);
-        
-        Ok(new_content)
-    } else {
-        // No frontmatter and no tags left, return original content
-        Ok(content.to_string())
-    }
+        Ok(properties)
+    })
 }
 
-/// Delete a category directory
-pub fn delete_category(dir: &Path, category_name: &str) -> Result<()> {
-    let category_path = dir.join(category_name);
+/// Delete a category directory.
+///
+/// Only empty categories are deleted, unless `recursive` is set, in which
+/// case the category is moved to the trash with all its notes and subcategories.
+pub fn delete_category(dir: &Path, category_name: &str, trash: &Trash, recursive: bool) -> Result<TrashEntry> {
+    let category_path = resolve_vault_path(dir, category_name)?;
     
     // Ensure the path exists and is a directory
     if !category_path.exists() {
-        return Err(anyhow::anyhow!("Category directory doesn't exist"));
+        return Err(io::Error::new(io::ErrorKind::NotFound, "Category directory doesn't exist").into());
     }
     
     if !category_path.is_dir() {
@@ -412,10 +657,122 @@ pub fn delete_category(dir: &Path, category_name: &str) -> Result<()> {
     // Check if the directory is empty
     let is_empty = category_path.read_dir()?.next().is_none();
     
-    if !is_empty {
-        return Err(anyhow::anyhow!("Cannot delete non-empty category. Move or delete files first."));
+    if !is_empty && !recursive {
+        let message = "Cannot delete non-empty category. Move or delete files first.";
+        return Err(io::Error::new(io::ErrorKind::DirectoryNotEmpty, message).into());
+    }
+    
+    // Move the directory to the trash
+    trash.delete(&category_path)
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // A vault with a `work` category and a note in it
+    fn vault() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("work")).unwrap();
+        fs::write(dir.path().join("work/note.md"), "# Note\n").unwrap();
+        dir
     }
     
-    // Delete the directory
-    fs::remove_dir(category_path).context("Failed to delete category directory")
-} 
\ No newline at end of file
+    #[test]
+    fn resolves_paths_inside_the_vault() {
+        let vault = vault();
+        let path = resolve_vault_path(vault.path(), "work/note.md").unwrap();
+        assert_eq!(path, vault.path().join("work/note.md"));
+        
+        let path = resolve_vault_path(vault.path(), "work/./sub/../note.md").unwrap();
+        assert_eq!(path, vault.path().join("work/note.md"));
+    }
+    
+    #[test]
+    fn resolves_new_files_under_an_existing_category() {
+        let vault = vault();
+        let path = resolve_vault_path(vault.path(), "work/new.md").unwrap();
+        assert_eq!(path, vault.path().join("work/new.md"));
+        
+        let path = resolve_vault_path(vault.path(), "work/new/deeper.md").unwrap();
+        assert_eq!(path, vault.path().join("work/new/deeper.md"));
+    }
+    
+    #[test]
+    fn rejects_parent_directory_escapes() {
+        let vault = vault();
+        for path in ["..", "../x.md", "a/../../x.md", "work/../../x.md"] {
+            assert!(matches!(resolve_vault_path(vault.path(), path), Err(VaultPathError::OutsideVault)), "{}", path);
+        }
+    }
+    
+    #[test]
+    fn rejects_absolute_paths() {
+        let vault = vault();
+        let absolute = vault.path().join("work/note.md");
+        for path in ["/etc/passwd", absolute.to_str().unwrap()] {
+            assert!(matches!(resolve_vault_path(vault.path(), path), Err(VaultPathError::Invalid(_))), "{}", path);
+        }
+    }
+    
+    #[test]
+    fn treats_backslashes_as_separators() {
+        let vault = vault();
+        let path = resolve_vault_path(vault.path(), "work\\note.md").unwrap();
+        assert_eq!(path, vault.path().join("work/note.md"));
+        
+        assert!(matches!(resolve_vault_path(vault.path(), "..\\x.md"), Err(VaultPathError::OutsideVault)));
+        assert!(matches!(resolve_vault_path(vault.path(), "\\etc\\passwd"), Err(VaultPathError::Invalid(_))));
+    }
+    
+    #[test]
+    fn rejects_empty_paths_and_nul_bytes() {
+        let vault = vault();
+        for path in ["", "  ", ".", "work/..", "work/note\0.md"] {
+            assert!(matches!(resolve_vault_path(vault.path(), path), Err(VaultPathError::Invalid(_))), "{:?}", path);
+        }
+    }
+    
+    #[test]
+    fn rejects_paths_into_the_data_directory() {
+        let vault = vault();
+        for path in [".mdlib", ".mdlib/history/x.md", "work/../.mdlib/trash"] {
+            assert!(matches!(resolve_vault_path(vault.path(), path), Err(VaultPathError::Invalid(_))), "{}", path);
+        }
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_out_of_the_vault() {
+        let vault = vault();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("secret.md"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.path(), vault.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.md"), vault.path().join("secret.md")).unwrap();
+        
+        for path in ["link", "link/secret.md", "link/new.md", "secret.md"] {
+            assert!(matches!(resolve_vault_path(vault.path(), path), Err(VaultPathError::OutsideVault)), "{}", path);
+        }
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn allows_symlinks_within_the_vault() {
+        let vault = vault();
+        std::os::unix::fs::symlink(vault.path().join("work"), vault.path().join("alias")).unwrap();
+        
+        let path = resolve_vault_path(vault.path(), "alias/note.md").unwrap();
+        assert_eq!(path, vault.path().join("alias/note.md"));
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn rejects_dangling_symlinks() {
+        let vault = vault();
+        std::os::unix::fs::symlink("/nonexistent/target", vault.path().join("dangling.md")).unwrap();
+        std::os::unix::fs::symlink("/nonexistent/dir", vault.path().join("dangling")).unwrap();
+        
+        for path in ["dangling.md", "dangling/new.md"] {
+            assert!(matches!(resolve_vault_path(vault.path(), path), Err(VaultPathError::OutsideVault)), "{}", path);
+        }
+    }
+}
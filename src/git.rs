@@ -0,0 +1,233 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::Mutex;
+use anyhow::{anyhow, Result, Context};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::config::GitConfig;
+use crate::fs::DATA_DIR;
+
+/// A commit that touched a note
+#[derive(Debug, Serialize)]
+pub struct Commit {
+    pub hash: String,
+    pub author: String,
+    pub email: String,
+    /// Author time in seconds since the Unix epoch
+    pub timestamp: i64,
+    pub message: String,
+}
+
+/// The commit that last changed a line of a note
+#[derive(Debug, Serialize)]
+pub struct BlameLine {
+    /// Line number in the current content, starting at 1
+    pub line: usize,
+    pub hash: String,
+    pub author: String,
+    pub email: String,
+    /// Author time in seconds since the Unix epoch
+    pub timestamp: i64,
+    pub summary: String,
+    pub content: String,
+}
+
+/// The git repository containing the notes directory.
+///
+/// Uses the `git` command line tool, so commits honour the user's own git
+/// configuration and hooks.
+pub struct GitRepo {
+    base_dir: PathBuf,
+    config: GitConfig,
+    // Only one commit can be in progress at a time, as git locks its index
+    commit_lock: Mutex<()>,
+}
+
+impl GitRepo {
+    /// Open the repository the notes directory belongs to, if there is one
+    pub fn open(base_dir: &Path, config: GitConfig) -> Option<Self> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(base_dir)
+            .args(["rev-parse", "--is-inside-work-tree"])
+            .output()
+            .ok()?;
+        
+        if !output.status.success() || String::from_utf8_lossy(&output.stdout).trim() != "true" {
+            return None;
+        }
+        
+        // Keep app data such as note history out of the repository
+        let data_dir = base_dir.join(DATA_DIR);
+        let ignore_file = data_dir.join(".gitignore");
+        if !ignore_file.exists() {
+            let ignored = std::fs::create_dir_all(&data_dir)
+                .and_then(|_| std::fs::write(&ignore_file, "*\n"));
+            if let Err(err) = ignored {
+                warn!("Failed to create {:?}: {}", ignore_file, err);
+            }
+        }
+        
+        Some(Self {
+            base_dir: base_dir.to_path_buf(),
+            config,
+            commit_lock: Mutex::new(()),
+        })
+    }
+    
+    /// Whether changes made through the API are committed automatically
+    pub fn auto_commit(&self) -> bool {
+        self.config.auto_commit
+    }
+    
+    /// Commit the current state of the given paths, relative to the notes directory.
+    ///
+    /// Only these paths are committed, whatever else is staged. Nothing is
+    /// committed if they haven't changed.
+    pub fn commit(&self, paths: &[&Path], message: &str) -> Result<()> {
+        let _guard = self.commit_lock.lock().unwrap_or_else(|e| e.into_inner());
+        
+        // git refuses pathspecs it knows nothing about, such as ignored files
+        // or files that were deleted before they were ever committed
+        let paths: Vec<&Path> = paths.iter().copied().filter(|path| self.is_known(path)).collect();
+        if paths.is_empty() {
+            return Ok(());
+        }
+        
+        let mut add = self.command();
+        add.args(["add", "--all", "--"]).args(&paths);
+        run(add).context("Failed to stage changes")?;
+        
+        // `git diff --quiet` exits with 1 if there are differences
+        let mut diff = self.command();
+        diff.args(["diff", "--cached", "--quiet", "--"]).args(&paths);
+        let output = diff.output().context("Failed to run git")?;
+        if output.status.success() {
+            return Ok(());
+        }
+        
+        let mut commit = self.command();
+        if let Some(name) = &self.config.author_name {
+            commit.arg("-c").arg(format!("user.name={}", name));
+        }
+        if let Some(email) = &self.config.author_email {
+            commit.arg("-c").arg(format!("user.email={}", email));
+        }
+        commit.args(["commit", "--quiet", "-m", message, "--"]).args(&paths);
+        run(commit).context("Failed to commit changes")?;
+        
+        Ok(())
+    }
+    
+    /// The commits that changed a note, newest first, following renames
+    pub fn log(&self, rel_path: &Path, limit: usize) -> Result<Vec<Commit>> {
+        let mut log = self.command();
+        log.args(["log", "--follow", "-z", "--format=%H%x1f%an%x1f%ae%x1f%at%x1f%B"])
+            .arg(format!("--max-count={}", limit))
+            .arg("--")
+            .arg(rel_path);
+        let output = run(log).context("Failed to read git log")?;
+        
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let commits = stdout
+            .split('\0')
+            .filter(|record| !record.trim().is_empty())
+            .filter_map(|record| {
+                let mut fields = record.trim_start_matches('\n').splitn(5, '\x1f');
+                Some(Commit {
+                    hash: fields.next()?.to_string(),
+                    author: fields.next()?.to_string(),
+                    email: fields.next()?.to_string(),
+                    timestamp: fields.next()?.parse().ok()?,
+                    message: fields.next()?.trim_end().to_string(),
+                })
+            })
+            .collect();
+        
+        Ok(commits)
+    }
+    
+    /// Who last changed each line of a note, including uncommitted changes
+    pub fn blame(&self, rel_path: &Path) -> Result<Vec<BlameLine>> {
+        let mut blame = self.command();
+        blame.args(["blame", "--line-porcelain", "--"]).arg(rel_path);
+        let output = run(blame).context("Failed to run git blame")?;
+        
+        Ok(parse_blame(&String::from_utf8_lossy(&output.stdout)))
+    }
+    
+    // Whether a path is tracked, or untracked but not ignored
+    fn is_known(&self, path: &Path) -> bool {
+        let mut ls_files = self.command();
+        ls_files.args(["ls-files", "--cached", "--others", "--exclude-standard", "--"]).arg(path);
+        run(ls_files)
+            .map(|output| !output.stdout.is_empty())
+            .unwrap_or(false)
+    }
+    
+    fn command(&self) -> Command {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.base_dir);
+        command
+    }
+}
+
+// Run a git command, turning a non-zero exit status into an error with git's message
+fn run(mut command: Command) -> Result<Output> {
+    let output = command.output().context("Failed to run git")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("git: {}", stderr.trim()));
+    }
+    Ok(output)
+}
+
+/// Parse the output of `git blame --line-porcelain`.
+///
+/// Every line of the file is introduced by `<hash> <orig line> <final line>`,
+/// followed by headers such as `author` and `summary`, and finally the line
+/// itself prefixed with a tab.
+fn parse_blame(output: &str) -> Vec<BlameLine> {
+    let mut lines = Vec::new();
+    let mut current: Option<BlameLine> = None;
+    
+    for line in output.lines() {
+        if let Some(content) = line.strip_prefix('\t') {
+            if let Some(mut blame_line) = current.take() {
+                blame_line.content = content.to_string();
+                lines.push(blame_line);
+            }
+            continue;
+        }
+        
+        match current.as_mut() {
+            None => {
+                let mut fields = line.split(' ');
+                let hash = fields.next().unwrap_or_default();
+                let line_number = fields.nth(1).and_then(|n| n.parse().ok()).unwrap_or(lines.len() + 1);
+                current = Some(BlameLine {
+                    line: line_number,
+                    hash: hash.to_string(),
+                    author: String::new(),
+                    email: String::new(),
+                    timestamp: 0,
+                    summary: String::new(),
+                    content: String::new(),
+                });
+            },
+            Some(blame_line) => {
+                let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+                match key {
+                    "author" => blame_line.author = value.to_string(),
+                    "author-mail" => blame_line.email = value.trim_matches(|c| c == '<' || c == '>').to_string(),
+                    "author-time" => blame_line.timestamp = value.parse().unwrap_or(0),
+                    "summary" => blame_line.summary = value.to_string(),
+                    _ => {},
+                }
+            },
+        }
+    }
+    
+    lines
+}
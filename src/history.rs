@@ -0,0 +1,273 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use tracing::warn;
+
+use crate::config::HistoryConfig;
+use crate::fs::{get_relative_path, now_millis, write_atomic, DATA_DIR};
+
+/// Why a revision was recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotReason {
+    /// The note was about to be overwritten
+    Update,
+    /// The note was about to be deleted
+    Delete,
+    /// The note was about to be replaced by an older revision
+    Restore,
+}
+
+impl SnapshotReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            SnapshotReason::Update => "update",
+            SnapshotReason::Delete => "delete",
+            SnapshotReason::Restore => "restore",
+        }
+    }
+    
+    fn parse(reason: &str) -> Option<Self> {
+        match reason {
+            "update" => Some(SnapshotReason::Update),
+            "delete" => Some(SnapshotReason::Delete),
+            "restore" => Some(SnapshotReason::Restore),
+            _ => None,
+        }
+    }
+}
+
+/// A stored revision of a note
+#[derive(Debug, Clone, Serialize)]
+pub struct Revision {
+    /// Identifier used to refer to the revision in the API
+    pub id: String,
+    /// When the revision was recorded, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub reason: SnapshotReason,
+    pub size: u64,
+}
+
+/// How to present the differences between two revisions
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffMode {
+    /// A unified diff of lines, like `diff -u`
+    #[default]
+    Unified,
+    /// A list of inserted, deleted and unchanged runs of words
+    Words,
+}
+
+/// The differences between two versions of a note
+#[derive(Debug, Serialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum NoteDiff {
+    Unified { diff: String },
+    Words { changes: Vec<WordChange> },
+}
+
+/// A run of words that is unchanged, inserted or deleted
+#[derive(Debug, Serialize)]
+pub struct WordChange {
+    /// `equal`, `insert` or `delete`
+    pub tag: &'static str,
+    pub text: String,
+}
+
+/// Per-note version history.
+///
+/// Revisions live in `.mdlib/history` inside the notes directory, in one
+/// directory per note (named after the note's relative path) holding a file
+/// per revision.
+pub struct History {
+    base_dir: PathBuf,
+    dir: PathBuf,
+    config: HistoryConfig,
+    /// Notes bigger than this many bytes are not snapshotted
+    max_file_size: u64,
+}
+
+impl History {
+    pub fn new(base_dir: &Path, config: HistoryConfig, max_file_size: u64) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            dir: base_dir.join(DATA_DIR).join("history"),
+            config,
+            max_file_size,
+        }
+    }
+    
+    /// Record the current content of a note before it is changed or deleted.
+    ///
+    /// Nothing is recorded if history is disabled, if the note doesn't exist
+    /// yet, if it is bigger than the file size limit, or if it hasn't changed
+    /// since the last revision.
+    pub fn snapshot(&self, path: &Path, reason: SnapshotReason) -> Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.len() > self.max_file_size => return Ok(()),
+            Ok(_) => {},
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err).context("Failed to read note for history"),
+        }
+        let content = fs::read_to_string(path).context("Failed to read note for history")?;
+        
+        let rel_path = get_relative_path(&self.base_dir, path)?;
+        let note_dir = self.dir.join(&rel_path);
+        
+        if let Some(latest) = self.revisions(&rel_path)?.first() {
+            if self.read(&rel_path, &latest.id)? == content {
+                return Ok(());
+            }
+        }
+        
+        fs::create_dir_all(&note_dir).context("Failed to create history directory")?;
+        
+        // Revisions are named after the time they were taken; make sure they stay unique
+        let mut timestamp = now_millis();
+        let mut revision_path;
+        loop {
+            revision_path = note_dir.join(format!("{}-{}.md", timestamp, reason.as_str()));
+            if !revision_path.exists() {
+                break;
+            }
+            timestamp += 1;
+        }
+        write_atomic(&revision_path, content.as_bytes()).context("Failed to write history revision")?;
+        
+        self.prune(&rel_path);
+        Ok(())
+    }
+    
+    /// All revisions of a note, newest first
+    pub fn revisions(&self, rel_path: &Path) -> Result<Vec<Revision>> {
+        let entries = match fs::read_dir(self.dir.join(rel_path)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err).context("Failed to read history directory"),
+        };
+        
+        let mut revisions: Vec<Revision> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name();
+                let id = file_name.to_str()?.strip_suffix(".md")?;
+                let (timestamp, reason) = parse_revision_id(id)?;
+                Some(Revision {
+                    id: id.to_string(),
+                    timestamp,
+                    reason,
+                    size: entry.metadata().ok()?.len(),
+                })
+            })
+            .collect();
+        
+        revisions.sort_by_key(|revision| std::cmp::Reverse(revision.timestamp));
+        Ok(revisions)
+    }
+    
+    /// The content of a note at a given revision
+    pub fn read(&self, rel_path: &Path, id: &str) -> Result<String> {
+        if parse_revision_id(id).is_none() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Revision not found").into());
+        }
+        
+        let revision_path = self.dir.join(rel_path).join(format!("{}.md", id));
+        match fs::read_to_string(revision_path) {
+            Ok(content) => Ok(content),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                Err(io::Error::new(io::ErrorKind::NotFound, "Revision not found").into())
+            },
+            Err(err) => Err(err).context("Failed to read history revision"),
+        }
+    }
+    
+    /// Move the history of a note along with the note itself
+    pub fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let from_dir = self.dir.join(from);
+        if !from_dir.is_dir() {
+            return Ok(());
+        }
+        
+        let to_dir = self.dir.join(to);
+        fs::create_dir_all(&to_dir).context("Failed to create history directory")?;
+        for entry in fs::read_dir(&from_dir)?.filter_map(|e| e.ok()) {
+            fs::rename(entry.path(), to_dir.join(entry.file_name()))
+                .context("Failed to move history revision")?;
+        }
+        fs::remove_dir(&from_dir).context("Failed to remove old history directory")?;
+        Ok(())
+    }
+    
+    /// Drop revisions beyond the configured count or age
+    fn prune(&self, rel_path: &Path) {
+        let revisions = match self.revisions(rel_path) {
+            Ok(revisions) => revisions,
+            Err(err) => {
+                warn!("Failed to prune history of {:?}: {:#}", rel_path, err);
+                return;
+            }
+        };
+        
+        let max_age = Duration::from_secs(self.config.max_age_days * 24 * 60 * 60);
+        let cutoff = now_millis().saturating_sub(max_age.as_millis() as u64);
+        
+        for (position, revision) in revisions.iter().enumerate() {
+            let too_many = self.config.max_revisions > 0 && position >= self.config.max_revisions;
+            let too_old = self.config.max_age_days > 0 && revision.timestamp < cutoff;
+            if too_many || too_old {
+                let revision_path = self.dir.join(rel_path).join(format!("{}.md", revision.id));
+                if let Err(err) = fs::remove_file(&revision_path) {
+                    warn!("Failed to remove old revision {:?}: {}", revision_path, err);
+                }
+            }
+        }
+    }
+}
+
+/// Compare two versions of a note
+pub fn diff(old: &str, new: &str, mode: DiffMode, old_label: &str, new_label: &str) -> NoteDiff {
+    match mode {
+        DiffMode::Unified => {
+            let diff = TextDiff::from_lines(old, new)
+                .unified_diff()
+                .context_radius(3)
+                .header(old_label, new_label)
+                .to_string();
+            NoteDiff::Unified { diff }
+        },
+        DiffMode::Words => {
+            let mut changes: Vec<WordChange> = Vec::new();
+            for change in TextDiff::from_words(old, new).iter_all_changes() {
+                let tag = match change.tag() {
+                    ChangeTag::Equal => "equal",
+                    ChangeTag::Insert => "insert",
+                    ChangeTag::Delete => "delete",
+                };
+                // Join consecutive words with the same tag into a single run
+                match changes.last_mut() {
+                    Some(last) if last.tag == tag => last.text.push_str(change.value()),
+                    _ => changes.push(WordChange { tag, text: change.value().to_string() }),
+                }
+            }
+            NoteDiff::Words { changes }
+        },
+    }
+}
+
+/// Split a revision id such as `1718000000000-update` into its timestamp and reason
+fn parse_revision_id(id: &str) -> Option<(u64, SnapshotReason)> {
+    let (timestamp, reason) = id.split_once('-')?;
+    if timestamp.is_empty() || !timestamp.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((timestamp.parse().ok()?, SnapshotReason::parse(reason)?))
+}
@@ -0,0 +1,400 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::config::{FormatsConfig, TagsConfig};
+use crate::fs::{self, MarkdownFile};
+use crate::format::NoteFormat;
+use crate::search::SearchIndex;
+use crate::query::{Query, QueryMatch};
+use crate::property_query::{PropertyQuery, QueryResult};
+use crate::render;
+use crate::scan::{self, VaultScanner};
+use crate::tags::{self, TagNode};
+use crate::links::{self, Backlink, Link, LinkPlan, LinkResolver, ResolvedLink};
+
+/// In-memory index of all notes in the base directory.
+///
+/// The index is built once at startup and then kept up to date incrementally,
+/// either by handlers after they change a file or by the file watcher.
+pub struct NoteIndex {
+    base_dir: PathBuf,
+    /// Notes bigger than this many bytes are listed but not read
+    max_file_size: u64,
+    tags_config: TagsConfig,
+    /// Decides which files and directories are part of the vault
+    scanner: VaultScanner,
+    data: RwLock<IndexData>,
+}
+
+#[derive(Default)]
+struct IndexData {
+    /// Notes keyed by their path relative to the base directory
+    notes: BTreeMap<PathBuf, MarkdownFile>,
+    /// Category directories at any depth, so that empty categories are listed too
+    directories: BTreeSet<String>,
+    /// Full-text index over note contents
+    search: SearchIndex,
+    /// Outgoing links of each note
+    links: BTreeMap<PathBuf, Vec<Link>>,
+}
+
+/// A category in the folder hierarchy
+#[derive(Debug, Serialize)]
+pub struct CategoryNode {
+    /// The directory name, e.g. `acme`
+    pub name: String,
+    /// The full category, e.g. `work/clients/acme`
+    pub path: String,
+    /// Notes directly in this category
+    pub count: usize,
+    /// Notes in this category or any category below it
+    pub total: usize,
+    pub children: Vec<CategoryNode>,
+}
+
+// Category tree under construction, with children sorted by name
+#[derive(Default)]
+struct CategoryTreeBuilder {
+    count: usize,
+    total: usize,
+    children: BTreeMap<String, CategoryTreeBuilder>,
+}
+
+impl CategoryTreeBuilder {
+    fn node(&mut self, category: &str) -> &mut CategoryTreeBuilder {
+        category.split(['/', '\\'])
+            .filter(|part| !part.is_empty())
+            .fold(self, |node, part| node.children.entry(part.to_string()).or_default())
+    }
+    
+    fn into_nodes(self, parent: &str) -> Vec<CategoryNode> {
+        self.children.into_iter()
+            .map(|(name, builder)| {
+                let path = if parent.is_empty() { name.clone() } else { format!("{}/{}", parent, name) };
+                CategoryNode {
+                    count: builder.count,
+                    total: builder.total,
+                    children: builder.into_nodes(&path),
+                    name,
+                    path,
+                }
+            })
+            .collect()
+    }
+}
+
+impl IndexData {
+    fn insert(&mut self, file: MarkdownFile, content: &str, formats: &FormatsConfig) {
+        self.search.insert(&file, content);
+        self.links.insert(file.path.clone(), links::parse_links(content, formats));
+        self.notes.insert(file.path.clone(), file);
+    }
+    
+    fn remove(&mut self, path: &Path) {
+        self.notes.remove(path);
+        self.search.remove(path);
+        self.links.remove(path);
+    }
+    
+    fn resolver<'a>(&'a self, formats: &'a FormatsConfig) -> LinkResolver<'a> {
+        LinkResolver::new(self.notes.keys().map(PathBuf::as_path), formats)
+    }
+}
+
+impl NoteIndex {
+    /// Build the index by scanning the whole base directory
+    pub fn build(base_dir: &Path, max_file_size: u64, tags_config: TagsConfig, scanner: VaultScanner) -> Self {
+        let index = Self {
+            base_dir: base_dir.to_path_buf(),
+            max_file_size,
+            tags_config,
+            scanner,
+            data: RwLock::new(IndexData::default()),
+        };
+        index.rebuild();
+        index
+    }
+    
+    /// Rescan the whole base directory, replacing the current contents.
+    /// Notes that can't be read are left out.
+    pub fn rebuild(&self) {
+        let mut fresh = IndexData {
+            directories: self.category_directories(&self.base_dir),
+            ..IndexData::default()
+        };
+        for path in self.scanner.note_files(&self.base_dir) {
+            // One unreadable note shouldn't keep the rest from being indexed
+            match fs::load_markdown_file(&self.base_dir, &path, self.max_file_size, &self.tags_config) {
+                Ok((file, content)) => fresh.insert(file, &content, self.scanner.formats()),
+                Err(err) => warn!("Skipping {:?} while indexing: {:#}", path, err),
+            }
+        }
+        
+        debug!("Indexed {} notes", fresh.notes.len());
+        *self.write() = fresh;
+    }
+    
+    /// All notes, sorted by name
+    pub fn files(&self) -> Vec<MarkdownFile> {
+        let mut files: Vec<MarkdownFile> = self.read().notes.values().cloned().collect();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        files
+    }
+    
+    /// Find a note by file name, ignoring case
+    pub fn find_by_name(&self, file_name: &str) -> Vec<MarkdownFile> {
+        let file_name = file_name.to_lowercase();
+        self.read().notes.values()
+            .filter(|file| file.name.to_lowercase() == file_name)
+            .cloned()
+            .collect()
+    }
+    
+    /// Run a parsed search query against all notes
+    pub fn query(&self, query: &Query) -> Vec<QueryMatch> {
+        let data = self.read();
+        query.evaluate(data.notes.values(), &data.search)
+    }
+    
+    /// Run a query block, such as a table of notes by their properties, against all notes
+    pub fn property_query(&self, query: &PropertyQuery) -> QueryResult {
+        let data = self.read();
+        query.evaluate(data.notes.values(), &data.search)
+    }
+    
+    /// Outgoing links of a note, each with the note it resolves to
+    pub fn links(&self, path: &Path) -> Vec<ResolvedLink> {
+        let data = self.read();
+        let resolver = data.resolver(self.scanner.formats());
+        data.links.get(path)
+            .map(|links| links.iter()
+                .map(|link| ResolvedLink {
+                    link: link.clone(),
+                    resolved: resolver.resolve(path, link),
+                })
+                .collect())
+            .unwrap_or_default()
+    }
+    
+    /// Notes linking to the given note, sorted by name
+    pub fn backlinks(&self, path: &Path) -> Vec<Backlink> {
+        let data = self.read();
+        let resolver = data.resolver(self.scanner.formats());
+        let mut backlinks: Vec<Backlink> = data.links.iter()
+            .filter(|(source, _)| source.as_path() != path)
+            .filter_map(|(source, links)| {
+                let matching: Vec<Link> = links.iter()
+                    .filter(|link| resolver.resolve(source, link).as_deref() == Some(path))
+                    .cloned()
+                    .collect();
+                if matching.is_empty() {
+                    return None;
+                }
+                Some(Backlink {
+                    source: data.notes.get(source)?.clone(),
+                    links: matching,
+                })
+            })
+            .collect();
+        backlinks.sort_by(|a, b| a.source.name.cmp(&b.source.name));
+        backlinks
+    }
+    
+    /// Work out how links in all notes must change when the note or category
+    /// at `from` moves to `to` (both relative to the base directory).
+    ///
+    /// The plan holds the rewritten content of every affected note; nothing is written.
+    pub fn plan_link_updates(&self, from: &Path, to: &Path) -> LinkPlan {
+        // Only notes with a link that would end up pointing somewhere else are
+        // read, and only after the lock is released
+        let (mut plan, affected) = {
+            let data = self.read();
+            let plan = LinkPlan::new(from, to, data.notes.keys().cloned().collect(), self.scanner.formats().clone());
+            let rewriter = plan.rewriter();
+            let affected: Vec<PathBuf> = data.links.iter()
+                .filter(|(path, links)| rewriter.is_affected(path, links))
+                .map(|(path, _)| path.clone())
+                .collect();
+            drop(rewriter);
+            (plan, affected)
+        };
+        
+        let rewriter = plan.rewriter();
+        let updates = affected.iter()
+            .filter_map(|path| match fs::read_markdown_file(&self.base_dir.join(path), self.max_file_size) {
+                Ok(content) => rewriter.rewrite(path, &content),
+                Err(err) => {
+                    warn!("Failed to read {:?} for link updates: {:#}", path, err);
+                    None
+                },
+            })
+            .collect();
+        drop(rewriter);
+        plan.updates = updates;
+        plan
+    }
+    
+    /// Render a note to HTML, resolving its wiki links against the index.
+    /// Plain text and Org mode notes are shown as they are written.
+    pub fn render(&self, source: &Path, content: &str) -> String {
+        if NoteFormat::of(source) != NoteFormat::Markdown {
+            return render::render_plain_text(content);
+        }
+        
+        let data = self.read();
+        render::render_markdown(content, source, &data.resolver(self.scanner.formats()), |query| query.evaluate(data.notes.values(), &data.search))
+    }
+    
+    /// All tags used by notes, nested by their `/`-separated parts
+    pub fn tag_tree(&self) -> Vec<TagNode> {
+        tags::tag_tree(self.read().notes.values())
+    }
+    
+    /// All categories, nested ones included: those used by notes plus
+    /// directories that don't contain notes (yet)
+    pub fn categories(&self) -> Vec<String> {
+        let data = self.read();
+        let mut categories: BTreeSet<String> = data.notes.values()
+            .filter_map(|file| file.category.clone())
+            .collect();
+        categories.extend(data.directories.iter().cloned());
+        categories.into_iter().collect()
+    }
+    
+    /// All categories as a tree of nested categories, with the number of notes in each
+    pub fn category_tree(&self) -> Vec<CategoryNode> {
+        let categories = self.categories();
+        let data = self.read();
+        
+        let mut root = CategoryTreeBuilder::default();
+        for category in &categories {
+            root.node(category);
+        }
+        for file in data.notes.values() {
+            let Some(category) = &file.category else {
+                continue;
+            };
+            root.node(category).count += 1;
+            
+            // Count the note towards its category and every one above it
+            let mut node = &mut root;
+            for part in category.split(['/', '\\']).filter(|part| !part.is_empty()) {
+                node = node.children.entry(part.to_string()).or_default();
+                node.total += 1;
+            }
+        }
+        
+        root.into_nodes("")
+    }
+    
+    /// Bring the index up to date for a single path after it changed on disk.
+    ///
+    /// The path may be absolute or relative to the base directory, and may
+    /// point at a note, a directory, or something that no longer exists.
+    pub fn refresh(&self, path: &Path) {
+        let abs_path = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.base_dir.join(path)
+        };
+        let rel_path = match fs::get_relative_path(&self.base_dir, &abs_path) {
+            Ok(rel_path) => rel_path,
+            // Paths that were removed can't be canonicalized, so fall back to a plain prefix strip
+            Err(_) => match abs_path.strip_prefix(&self.base_dir) {
+                Ok(rel_path) => rel_path.to_path_buf(),
+                Err(_) => return,
+            },
+        };
+        
+        if rel_path.as_os_str().is_empty() {
+            self.rebuild();
+            return;
+        }
+        
+        // Changed ignore rules can add or hide notes anywhere next to or below them
+        if rel_path.file_name().is_some_and(scan::is_ignore_file) {
+            let dir = rel_path.parent().unwrap_or(Path::new(""));
+            self.scanner.forget_ignore_rules(dir);
+            self.remove(dir);
+            self.refresh(dir);
+            return;
+        }
+        
+        let ignored = self.scanner.is_ignored(&rel_path, abs_path.is_dir());
+        if abs_path.is_dir() && !ignored {
+            // A directory appeared (or was moved in): index everything inside it
+            let loaded: Vec<(MarkdownFile, String)> = self.scanner.note_files(&abs_path)
+                .iter()
+                .filter_map(|path| fs::load_markdown_file(&self.base_dir, path, self.max_file_size, &self.tags_config).ok())
+                .collect();
+            let mut data = self.write();
+            data.directories.insert(rel_path.to_string_lossy().into_owned());
+            data.directories.extend(self.category_directories(&abs_path));
+            for (file, content) in loaded {
+                data.insert(file, &content, self.scanner.formats());
+            }
+        } else if abs_path.is_file() && !ignored {
+            if !self.scanner.is_note_file(&abs_path) {
+                return;
+            }
+            match fs::load_markdown_file(&self.base_dir, &abs_path, self.max_file_size, &self.tags_config) {
+                Ok((file, content)) => {
+                    self.write().insert(file, &content, self.scanner.formats());
+                },
+                Err(err) => warn!("Failed to index {:?}: {:#}", abs_path, err),
+            }
+        } else {
+            // Gone or ignored: drop the note itself or everything below a directory
+            self.remove(&rel_path);
+        }
+    }
+    
+    /// Whether a path names a note by its extension
+    pub fn is_note_file(&self, path: &Path) -> bool {
+        self.scanner.is_note_file(path)
+    }
+    
+    /// Whether a path is left out of the vault by the ignore rules
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.scanner.is_ignored(path, is_dir)
+    }
+    
+    /// Read the ignore files in `dir` again the next time they are needed
+    pub fn forget_ignore_rules(&self, dir: &Path) {
+        self.scanner.forget_ignore_rules(dir);
+    }
+    
+    // Helper function to drop a note, or everything below a directory, from the index
+    fn remove(&self, rel_path: &Path) {
+        let mut data = self.write();
+        let removed: Vec<PathBuf> = data.notes.keys()
+            .filter(|path| path.starts_with(rel_path))
+            .cloned()
+            .collect();
+        for path in removed {
+            data.remove(&path);
+        }
+        data.directories.retain(|dir| !Path::new(dir).starts_with(rel_path));
+    }
+    
+    /// Category directories below `dir`, relative to the base directory: every
+    /// directory the scanner doesn't skip, so not those holding attachments
+    fn category_directories(&self, dir: &Path) -> BTreeSet<String> {
+        self.scanner.directories(dir)
+            .iter()
+            .filter_map(|path| path.strip_prefix(&self.base_dir).ok())
+            .map(|rel_path| rel_path.to_string_lossy().into_owned())
+            .collect()
+    }
+    
+    fn read(&self) -> RwLockReadGuard<'_, IndexData> {
+        self.data.read().unwrap_or_else(|err| err.into_inner())
+    }
+    
+    fn write(&self) -> RwLockWriteGuard<'_, IndexData> {
+        self.data.write().unwrap_or_else(|err| err.into_inner())
+    }
+}
@@ -0,0 +1,711 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use serde::Serialize;
+
+use crate::config::FormatsConfig;
+use crate::format;
+use crate::fs::MarkdownFile;
+
+/// The syntax a link was written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    /// `[[Note]]`, `[[Note|alias]]` or `[[Note#Heading]]`
+    Wiki,
+    /// `[text](relative/path.md)`
+    Markdown,
+}
+
+/// A link from one note to another, as written in the note
+#[derive(Debug, Clone, Serialize)]
+pub struct Link {
+    pub kind: LinkKind,
+    /// The linked note as written, without heading or alias
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    /// Display text: the wiki alias or the markdown link text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// 1-based line number
+    pub line: usize,
+    /// The line containing the link
+    pub context: String,
+    /// Byte range of `target` within the note
+    #[serde(skip)]
+    pub target_range: Range<usize>,
+    /// Byte range of the whole link syntax within the note
+    #[serde(skip)]
+    pub range: Range<usize>,
+}
+
+/// An outgoing link together with the note it points to, if any
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedLink {
+    #[serde(flatten)]
+    pub link: Link,
+    /// Path of the linked note relative to the base directory
+    pub resolved: Option<PathBuf>,
+}
+
+/// A note linking to another note, with every link it contains to that note
+#[derive(Debug, Clone, Serialize)]
+pub struct Backlink {
+    pub source: MarkdownFile,
+    pub links: Vec<Link>,
+}
+
+/// A note whose links have to change because another note moved
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkUpdate {
+    /// Path of the note after the move
+    pub path: PathBuf,
+    /// Path of the note before the move
+    #[serde(skip)]
+    pub source: PathBuf,
+    pub changes: Vec<LineChange>,
+    /// The rewritten note
+    #[serde(skip)]
+    pub content: String,
+}
+
+/// A single line rewritten by a `LinkUpdate`
+#[derive(Debug, Clone, Serialize)]
+pub struct LineChange {
+    /// 1-based line number
+    pub line: usize,
+    pub before: String,
+    pub after: String,
+}
+
+impl LinkUpdate {
+    pub fn new(source: PathBuf, path: PathBuf, old_content: &str, content: String) -> Self {
+        // Link targets never span lines, so the lines still correspond one to one
+        let changes = old_content.lines()
+            .zip(content.lines())
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(index, (before, after))| LineChange {
+                line: index + 1,
+                before: before.to_string(),
+                after: after.to_string(),
+            })
+            .collect();
+        Self { path, source, changes, content }
+    }
+}
+
+/// The notes whose links have to change because a note or category moved
+/// from `from` to `to`, worked out from the notes in the vault at the time.
+///
+/// Keeps what it takes to rewrite links again, so notes can be re-read
+/// right before they are written.
+pub struct LinkPlan {
+    from: PathBuf,
+    to: PathBuf,
+    /// Every note before the move
+    before: Vec<PathBuf>,
+    /// Every note after the move
+    after: Vec<PathBuf>,
+    formats: FormatsConfig,
+    pub updates: Vec<LinkUpdate>,
+}
+
+impl LinkPlan {
+    /// A plan without updates yet for moving `from` to `to`, given every
+    /// note before the move (all paths relative to the base directory)
+    pub fn new(from: &Path, to: &Path, notes: Vec<PathBuf>, formats: FormatsConfig) -> Self {
+        let after = notes.iter()
+            .map(|path| moved_path(path, from, to))
+            .collect();
+        Self {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            before: notes,
+            after,
+            formats,
+            updates: Vec::new(),
+        }
+    }
+    
+    pub fn rewriter(&self) -> LinkRewriter<'_> {
+        LinkRewriter {
+            from: &self.from,
+            to: &self.to,
+            before: LinkResolver::new(self.before.iter().map(PathBuf::as_path), &self.formats),
+            after: LinkResolver::new(self.after.iter().map(PathBuf::as_path), &self.formats),
+        }
+    }
+}
+
+/// Rewrites the links in notes for a `LinkPlan`
+pub struct LinkRewriter<'a> {
+    from: &'a Path,
+    to: &'a Path,
+    before: LinkResolver<'a>,
+    after: LinkResolver<'a>,
+}
+
+impl LinkRewriter<'_> {
+    /// Where a note ends up after the move
+    pub fn moved(&self, path: &Path) -> PathBuf {
+        moved_path(path, self.from, self.to)
+    }
+    
+    /// Whether any of the links in the note at `source` would point
+    /// somewhere else after the move
+    pub fn is_affected(&self, source: &Path, links: &[Link]) -> bool {
+        let new_path = self.moved(source);
+        links.iter().any(|link| {
+            self.before.resolve(source, link)
+                .map(|target| self.moved(&target))
+                .is_some_and(|target| !self.after.resolves_to(&new_path, link, &target))
+        })
+    }
+    
+    /// Rewrite the links in `content`, the note at `source` before the move;
+    /// returns `None` if nothing needs to change
+    pub fn rewrite(&self, source: &Path, content: &str) -> Option<LinkUpdate> {
+        let new_path = self.moved(source);
+        let updated = rewrite_links(content, (source, &new_path), &self.before, &self.after, (self.from, self.to))?;
+        Some(LinkUpdate::new(source.to_path_buf(), new_path, content, updated))
+    }
+}
+
+/// Find all wiki and relative markdown links in a note, skipping code.
+/// Markdown links count if they point at a file with a note extension.
+pub fn parse_links(content: &str, formats: &FormatsConfig) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut fence: Option<(u8, usize)> = None;
+    let mut offset = 0;
+    
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        let line_start = offset;
+        offset += line.len();
+        let text = line.trim_end_matches(['\n', '\r']);
+        
+        if let Some((marker, len)) = fence_marker(text) {
+            match fence {
+                None => fence = Some((marker, len)),
+                Some((open, open_len)) if marker == open && len >= open_len => fence = None,
+                _ => {},
+            }
+            continue;
+        }
+        if fence.is_none() {
+            parse_line(text, line_start, index + 1, formats, &mut links);
+        }
+    }
+    
+    links
+}
+
+/// The fence character and length if the line opens or closes a code block
+fn fence_marker(line: &str) -> Option<(u8, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    
+    let marker = *trimmed.as_bytes().first()?;
+    if marker != b'`' && marker != b'~' {
+        return None;
+    }
+    let len = trimmed.bytes().take_while(|&b| b == marker).count();
+    (len >= 3).then_some((marker, len))
+}
+
+fn parse_line(text: &str, line_start: usize, line: usize, formats: &FormatsConfig, links: &mut Vec<Link>) {
+    let bytes = text.as_bytes();
+    let code_spans = inline_code_spans(bytes);
+    let context = text.trim();
+    
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(span) = code_spans.iter().find(|span| span.contains(&i)) {
+            i = span.end;
+            continue;
+        }
+        
+        if bytes[i..].starts_with(b"[[") {
+            let inner_start = i + 2;
+            if let Some(len) = text[inner_start..].find("]]") {
+                if let Some(mut link) = wiki_link(&text[inner_start..inner_start + len], inner_start) {
+                    link.line = line;
+                    link.context = context.to_string();
+                    link.target_range = link.target_range.start + line_start..link.target_range.end + line_start;
+                    link.range = i + line_start..inner_start + len + 2 + line_start;
+                    links.push(link);
+                }
+                i = inner_start + len + 2;
+                continue;
+            }
+        } else if bytes[i] == b'[' {
+            if let Some((mut link, end)) = markdown_link(text, i, formats) {
+                link.line = line;
+                link.context = context.to_string();
+                link.target_range = link.target_range.start + line_start..link.target_range.end + line_start;
+                link.range = i + line_start..end + line_start;
+                links.push(link);
+                i = end;
+                continue;
+            }
+        }
+        
+        i += 1;
+    }
+}
+
+/// Byte ranges of inline code spans, delimited by equal runs of backticks
+fn inline_code_spans(bytes: &[u8]) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        if bytes[i] != b'`' {
+            i += 1;
+            continue;
+        }
+        
+        let start = i;
+        while i < bytes.len() && bytes[i] == b'`' {
+            i += 1;
+        }
+        let run = i - start;
+        
+        // Look for a closing run of the same length
+        let mut j = i;
+        while j < bytes.len() {
+            if bytes[j] == b'`' {
+                let close_start = j;
+                while j < bytes.len() && bytes[j] == b'`' {
+                    j += 1;
+                }
+                if j - close_start == run {
+                    spans.push(start..j);
+                    i = j;
+                    break;
+                }
+            } else {
+                j += 1;
+            }
+        }
+    }
+    
+    spans
+}
+
+/// Parse the inside of `[[...]]`, which starts at byte `start` of the line
+fn wiki_link(inner: &str, start: usize) -> Option<Link> {
+    let (reference, alias) = match inner.split_once('|') {
+        // Inside tables the pipe is escaped as `\|`
+        Some((reference, alias)) => (reference.strip_suffix('\\').unwrap_or(reference), Some(alias.trim())),
+        None => (inner, None),
+    };
+    let (target, heading) = match reference.split_once('#') {
+        Some((target, heading)) => (target, Some(heading.trim())),
+        None => (reference, None),
+    };
+    
+    // `[[#Heading]]` points into the same note
+    let trimmed = target.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let target_start = start + (target.len() - target.trim_start().len());
+    
+    Some(Link {
+        kind: LinkKind::Wiki,
+        target: trimmed.to_string(),
+        heading: heading.filter(|h| !h.is_empty()).map(str::to_string),
+        alias: alias.filter(|a| !a.is_empty()).map(str::to_string),
+        line: 0,
+        context: String::new(),
+        target_range: target_start..target_start + trimmed.len(),
+        range: 0..0,
+    })
+}
+
+/// Parse `[text](destination)` starting at the `[` at byte `start`.
+///
+/// Returns the link and the byte just past it, or `None` if this is not a
+/// link to a local note.
+fn markdown_link(text: &str, start: usize, formats: &FormatsConfig) -> Option<(Link, usize)> {
+    let bytes = text.as_bytes();
+    
+    // Find the matching `]`, allowing nested brackets in the link text
+    let mut depth = 0;
+    let mut close = None;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
+                }
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+    let close = close?;
+    if bytes.get(close + 1) != Some(&b'(') {
+        return None;
+    }
+    
+    // The destination is either `<...>` or runs until whitespace or the closing paren
+    let mut dest_start = close + 2;
+    while bytes.get(dest_start) == Some(&b' ') {
+        dest_start += 1;
+    }
+    let dest_end;
+    let mut i = dest_start;
+    if bytes.get(i) == Some(&b'<') {
+        dest_start += 1;
+        dest_end = dest_start + text[dest_start..].find('>')?;
+        i = dest_end + 1;
+    } else {
+        let mut depth = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'(' => depth += 1,
+                b')' if depth == 0 => break,
+                b')' => depth -= 1,
+                b if b.is_ascii_whitespace() => break,
+                _ => {},
+            }
+            i += 1;
+        }
+        dest_end = i;
+    }
+    
+    // Skip an optional title up to the closing paren
+    let end = i + text[i..].find(')')? + 1;
+    
+    let destination = &text[dest_start..dest_end];
+    let (path, heading) = match destination.split_once('#') {
+        Some((path, heading)) => (path, Some(heading)),
+        None => (destination, None),
+    };
+    if !is_local_note_link(path, formats) {
+        return None;
+    }
+    
+    let link_text = text[start + 1..close].trim();
+    let link = Link {
+        kind: LinkKind::Markdown,
+        target: path.to_string(),
+        heading: heading.filter(|h| !h.is_empty()).map(percent_decode),
+        alias: (!link_text.is_empty()).then(|| link_text.to_string()),
+        line: 0,
+        context: String::new(),
+        target_range: dest_start..dest_start + path.len(),
+        range: 0..0,
+    };
+    Some((link, end))
+}
+
+/// Whether a markdown link destination points at a note in the vault rather
+/// than a web page, an anchor or some other file
+fn is_local_note_link(path: &str, formats: &FormatsConfig) -> bool {
+    is_local_path(path) && format::is_note_link(path, formats)
+}
+
+/// Whether a link destination is a path to a file in the vault rather than
+/// a web page, an anchor or another kind of URL
+pub fn is_local_path(path: &str) -> bool {
+    if path.is_empty() || path.starts_with('#') || path.contains("://") || path.starts_with("//") {
+        return false;
+    }
+    
+    // Reject URI schemes such as `mailto:`
+    if let Some((scheme, _)) = path.split_once(':') {
+        if scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')) {
+            return false;
+        }
+    }
+    
+    true
+}
+
+/// Decode `%XX` escapes, leaving anything malformed untouched
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        let escaped = bytes[i] == b'%'
+            && bytes.get(i + 1).is_some_and(u8::is_ascii_hexdigit)
+            && bytes.get(i + 2).is_some_and(u8::is_ascii_hexdigit);
+        if escaped {
+            if let Ok(byte) = u8::from_str_radix(&text[i + 1..i + 3], 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Resolves link targets to notes.
+///
+/// Targets are first treated as paths; failing that, notes are matched by
+/// file name ignoring case, like files requested through the API.
+pub struct LinkResolver<'a> {
+    /// Lowercased relative paths
+    paths: HashMap<String, &'a Path>,
+    /// Lowercased file names without their extension
+    names: HashMap<String, Vec<&'a Path>>,
+    /// Lowercased extensions of the notes, `md` first, tried in order for
+    /// links without one
+    extensions: Vec<String>,
+    /// Which extensions make a file a note
+    formats: &'a FormatsConfig,
+}
+
+impl<'a> LinkResolver<'a> {
+    pub fn new(notes: impl Iterator<Item = &'a Path>, formats: &'a FormatsConfig) -> Self {
+        let mut paths = HashMap::new();
+        let mut names: HashMap<String, Vec<&'a Path>> = HashMap::new();
+        let mut extensions: Vec<String> = Vec::new();
+        
+        for path in notes {
+            paths.insert(path_key(path), path);
+            if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
+                names.entry(name.to_lowercase())
+                    .or_default()
+                    .push(path);
+            }
+            if let Some(extension) = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase) {
+                if !extensions.contains(&extension) {
+                    extensions.push(extension);
+                }
+            }
+        }
+        extensions.sort_by_key(|extension| (extension != "md", extension.clone()));
+        
+        Self { paths, names, extensions, formats }
+    }
+    
+    pub fn formats(&self) -> &'a FormatsConfig {
+        self.formats
+    }
+    
+    /// The note a link in `source` points to, relative to the base directory
+    pub fn resolve(&self, source: &Path, link: &Link) -> Option<PathBuf> {
+        if let Some(path) = self.resolve_path(source, link) {
+            return Some(path);
+        }
+        
+        // Fall back to matching the file name anywhere in the vault
+        let target = link_target(link);
+        let target_lower = format::strip_note_extension(&target.to_lowercase(), self.formats).trim_start_matches('/').to_string();
+        let name = target_lower.rsplit('/').next()?;
+        let candidates = self.names.get(name)?;
+        let source_dir = source.parent();
+        
+        // Prefer notes whose path ends with the target, then ones next to the source, then shallow ones
+        candidates.iter()
+            .min_by_key(|path| {
+                let key = path_key(path);
+                let ends_with_target = strip_extension(&key).ends_with(&target_lower);
+                (!ends_with_target, path.parent() != source_dir, path.components().count(), key)
+            })
+            .map(|path| path.to_path_buf())
+    }
+    
+    /// Whether a link from `source` leads to `target` the way other tools
+    /// would follow it: markdown links and wiki links containing a path must
+    /// match exactly, while bare wiki links may be resolved by name.
+    pub fn resolves_to(&self, source: &Path, link: &Link, target: &Path) -> bool {
+        let resolved = match link.kind {
+            LinkKind::Wiki if !link.target.contains(['/', '\\']) => self.resolve(source, link),
+            _ => self.resolve_path(source, link),
+        };
+        resolved.as_deref() == Some(target)
+    }
+    
+    /// Resolve a link as a path, without matching by name
+    fn resolve_path(&self, source: &Path, link: &Link) -> Option<PathBuf> {
+        let target = link_target(link);
+        
+        // Wiki links are relative to the base directory, markdown links to the note
+        let candidate = match link.kind {
+            LinkKind::Markdown if !target.starts_with('/') => {
+                normalize(&source.parent().unwrap_or(Path::new("")).join(&target))?
+            },
+            _ => normalize(Path::new(target.trim_start_matches('/')))?,
+        };
+        let key = path_key(&candidate);
+        self.paths.get(&key)
+            .or_else(|| self.extensions.iter().find_map(|extension| self.paths.get(&format!("{}.{}", key, extension))))
+            .map(|path| path.to_path_buf())
+    }
+}
+
+/// The link target with escapes removed and separators normalized
+fn link_target(link: &Link) -> String {
+    match link.kind {
+        LinkKind::Wiki => link.target.replace('\\', "/"),
+        LinkKind::Markdown => percent_decode(&link.target),
+    }
+}
+
+/// Lexically resolve `.` and `..`, refusing paths that leave the base directory
+pub fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut clean = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if !clean.pop() {
+                    return None;
+                }
+            },
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!clean.as_os_str().is_empty()).then_some(clean)
+}
+
+fn path_key(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/").to_lowercase()
+}
+
+// A note's path without its extension
+fn strip_extension(path: &str) -> &str {
+    match path.rsplit_once('.') {
+        Some((stem, extension)) if !extension.contains('/') => stem,
+        _ => path,
+    }
+}
+
+/// Where a note at `path` ends up when the note or category at `from` moves
+/// to `to`; notes elsewhere stay where they are
+pub fn moved_path(path: &Path, from: &Path, to: &Path) -> PathBuf {
+    match path.strip_prefix(from) {
+        Ok(rest) if rest.as_os_str().is_empty() => to.to_path_buf(),
+        Ok(rest) => to.join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Rewrite the links in a note so they keep pointing at the same notes after
+/// a note or category moved from `from` to `to`.
+///
+/// `note` is where the note containing `content` lives before and after the
+/// move (the two differ only for the moved note itself). `before` and `after`
+/// resolve links against the notes before and after the move. Links that
+/// still resolve correctly are left as written; returns `None` if nothing
+/// needs to change.
+pub fn rewrite_links(
+    content: &str,
+    note: (&Path, &Path),
+    before: &LinkResolver,
+    after: &LinkResolver,
+    (from, to): (&Path, &Path),
+) -> Option<String> {
+    let (old_note, new_note) = note;
+    let mut edits = Vec::new();
+    
+    for link in parse_links(content, before.formats) {
+        let Some(target) = before.resolve(old_note, &link) else {
+            // Leave broken links alone
+            continue;
+        };
+        let target = moved_path(&target, from, to);
+        if after.resolves_to(new_note, &link, &target) {
+            continue;
+        }
+        
+        let replacement = match link.kind {
+            LinkKind::Wiki => wiki_target(&link, new_note, &target, after),
+            LinkKind::Markdown => {
+                let path = if link.target.starts_with('/') {
+                    format!("/{}", path_to_slashes(&target))
+                } else {
+                    relative_path(new_note.parent().unwrap_or(Path::new("")), &target)
+                };
+                percent_encode_path(&path)
+            },
+        };
+        edits.push((link.target_range, replacement));
+    }
+    
+    if edits.is_empty() {
+        return None;
+    }
+    
+    // Apply from the back so earlier ranges stay valid
+    let mut content = content.to_string();
+    for (range, replacement) in edits.into_iter().rev() {
+        content.replace_range(range, &replacement);
+    }
+    Some(content)
+}
+
+/// The shortest wiki link target that resolves to `target`, keeping the
+/// extension if the original link used one
+fn wiki_target(link: &Link, note: &Path, target: &Path, after: &LinkResolver) -> String {
+    let keep_extension = format::is_note_link(&link.target, after.formats);
+    let full = path_to_slashes(target);
+    let full = if keep_extension { full } else { strip_extension(&full).to_string() };
+    let name = full.rsplit('/').next().unwrap_or(&full).to_string();
+    
+    if link.target.contains('/') {
+        return full;
+    }
+    
+    // Only use the bare name if it isn't ambiguous after the move
+    let mut candidate = link.clone();
+    candidate.target = name.clone();
+    if after.resolves_to(note, &candidate, target) {
+        name
+    } else {
+        full
+    }
+}
+
+/// Path of `target` relative to the directory `from_dir`, both relative to the base directory
+pub fn relative_path(from_dir: &Path, target: &Path) -> String {
+    let from: Vec<_> = from_dir.components().collect();
+    let to: Vec<_> = target.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    
+    let mut parts: Vec<String> = vec!["..".to_string(); from.len() - common];
+    parts.extend(to[common..].iter().map(|c| c.as_os_str().to_string_lossy().into_owned()));
+    parts.join("/")
+}
+
+fn path_to_slashes(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Escape the characters that would end or confuse a markdown link destination
+pub fn percent_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' ' => encoded.push_str("%20"),
+            '%' => encoded.push_str("%25"),
+            '(' => encoded.push_str("%28"),
+            ')' => encoded.push_str("%29"),
+            '<' => encoded.push_str("%3C"),
+            '>' => encoded.push_str("%3E"),
+            '#' => encoded.push_str("%23"),
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
@@ -5,6 +5,21 @@ mod fs;
 mod server;
 mod config;
 mod embedded;
+mod watcher;
+mod index;
+mod search;
+mod query;
+mod links;
+mod render;
+mod history;
+mod git;
+mod trash;
+mod attachments;
+mod frontmatter;
+mod property_query;
+mod tags;
+mod scan;
+mod format;
 
 #[tokio::main]
 async fn main() {
@@ -0,0 +1,205 @@
+use std::cmp::Ordering;
+use std::path::PathBuf;
+use serde::Serialize;
+use serde_yaml::Value;
+use thiserror::Error;
+
+use crate::frontmatter;
+use crate::fs::MarkdownFile;
+use crate::query::{Query, QueryError};
+use crate::search::SearchIndex;
+
+/// Errors in a query block
+#[derive(Debug, Error)]
+pub enum PropertyQueryError {
+    #[error("Unknown clause '{0}', expected TABLE, LIST, FROM, WHERE, SORT or LIMIT")]
+    UnknownClause(String),
+    #[error("Expected a category or #tag after FROM")]
+    EmptyFrom,
+    #[error("Invalid sort order '{0}', expected ASC or DESC")]
+    InvalidSortOrder(String),
+    #[error("Invalid limit '{0}'")]
+    InvalidLimit(String),
+    #[error(transparent)]
+    Filter(#[from] QueryError),
+}
+
+/// How the matching notes are presented
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryFormat {
+    List,
+    Table,
+}
+
+/// A query over the notes and their frontmatter, such as
+///
+/// ```text
+/// TABLE status, due
+/// FROM projects
+/// WHERE [status:active] -tag:archived
+/// SORT due ASC
+/// LIMIT 20
+/// ```
+///
+/// Every clause is optional and goes on its own line. `FROM` takes a category
+/// or a `#tag` (including nested categories or tags), and `WHERE` takes a search query (see [`Query`]). Columns and
+/// sort keys are frontmatter properties, or one of `name`, `path`, `title`,
+/// `category`, `tags`, `modified` and `size`.
+#[derive(Debug, Clone)]
+pub struct PropertyQuery {
+    format: QueryFormat,
+    columns: Vec<String>,
+    filter: Query,
+    sort: Vec<(String, bool)>,
+    limit: Option<usize>,
+}
+
+/// The notes matching a query, with the requested columns
+#[derive(Debug, Serialize)]
+pub struct QueryResult {
+    pub format: QueryFormat,
+    pub columns: Vec<String>,
+    pub rows: Vec<QueryRow>,
+}
+
+/// A note in the result of a query
+#[derive(Debug, Serialize)]
+pub struct QueryRow {
+    /// Path relative to the base directory
+    pub path: PathBuf,
+    /// The note's title, or its file name without extension
+    pub title: String,
+    /// One value per column; null where the note doesn't have the property
+    pub values: Vec<Value>,
+}
+
+impl PropertyQuery {
+    pub fn parse(input: &str) -> Result<Self, PropertyQueryError> {
+        let mut format = QueryFormat::List;
+        let mut columns = Vec::new();
+        let mut from = None;
+        let mut conditions = Vec::new();
+        let mut sort = Vec::new();
+        let mut limit = None;
+        
+        for line in input.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (clause, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            match clause.to_uppercase().as_str() {
+                "TABLE" | "LIST" => {
+                    format = if clause.eq_ignore_ascii_case("TABLE") { QueryFormat::Table } else { QueryFormat::List };
+                    columns = split_list(rest).map(str::to_string).collect();
+                },
+                "FROM" => {
+                    let source = rest.trim_matches('"');
+                    if source.is_empty() {
+                        return Err(PropertyQueryError::EmptyFrom);
+                    }
+                    from = Some(source.to_string());
+                },
+                "WHERE" => {
+                    if !rest.is_empty() {
+                        conditions.push(format!("({})", rest));
+                    }
+                },
+                "SORT" => {
+                    for key in split_list(rest) {
+                        let (key, order) = key.split_once(char::is_whitespace).unwrap_or((key, "ASC"));
+                        let descending = match order.trim().to_uppercase().as_str() {
+                            "ASC" => false,
+                            "DESC" => true,
+                            _ => return Err(PropertyQueryError::InvalidSortOrder(order.trim().to_string())),
+                        };
+                        sort.push((key.to_string(), descending));
+                    }
+                },
+                "LIMIT" => {
+                    limit = Some(rest.parse().map_err(|_| PropertyQueryError::InvalidLimit(rest.to_string()))?);
+                },
+                _ => return Err(PropertyQueryError::UnknownClause(clause.to_string())),
+            }
+        }
+        
+        let mut filter = Query::parse(&conditions.join(" "))?;
+        match from.as_deref() {
+            Some(tag) if tag.starts_with('#') => filter.require_tag(tag, true),
+            Some(category) => filter.require_category(category, true),
+            None => {},
+        }
+        
+        Ok(Self { format, columns, filter, sort, limit })
+    }
+    
+    /// Run the query against notes.
+    ///
+    /// Without a `SORT` clause notes are ordered the way search results are:
+    /// by relevance if the query contains text, and by name otherwise.
+    pub fn evaluate<'a>(
+        &self,
+        notes: impl Iterator<Item = &'a MarkdownFile>,
+        search: &SearchIndex,
+    ) -> QueryResult {
+        let mut matches = self.filter.evaluate(notes, search);
+        
+        if !self.sort.is_empty() {
+            matches.sort_by(|a, b| {
+                self.sort.iter()
+                    .map(|(key, descending)| {
+                        compare_column(&column_value(&a.file, key), &column_value(&b.file, key), *descending)
+                    })
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+        if let Some(limit) = self.limit {
+            matches.truncate(limit);
+        }
+        
+        let rows = matches.into_iter()
+            .map(|m| QueryRow {
+                title: m.file.title.clone().unwrap_or_else(|| {
+                    m.file.path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+                }),
+                values: self.columns.iter().map(|column| column_value(&m.file, column)).collect(),
+                path: m.file.path,
+            })
+            .collect();
+        
+        QueryResult {
+            format: self.format,
+            columns: self.columns.clone(),
+            rows,
+        }
+    }
+}
+
+/// The value of a column for a note
+fn column_value(file: &MarkdownFile, column: &str) -> Value {
+    let text = |text: &str| Value::String(text.to_string());
+    match column {
+        "name" => text(&file.name),
+        "path" => text(&file.path.to_string_lossy().replace('\\', "/")),
+        "title" => file.title.as_deref().map(text).unwrap_or(Value::Null),
+        "category" => file.category.as_deref().map(text).unwrap_or(Value::Null),
+        "tags" => Value::Sequence(file.tags.iter().map(|tag| text(tag)).collect()),
+        "modified" => file.modified.map(Value::from).unwrap_or(Value::Null),
+        "size" => Value::from(file.size),
+        _ => file.properties.get(column).cloned().unwrap_or(Value::Null),
+    }
+}
+
+// Notes without a value always come last, whatever the sort order
+fn compare_column(a: &Value, b: &Value, descending: bool) -> Ordering {
+    match (a.is_null(), b.is_null()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) if descending => frontmatter::compare(b, a),
+        (false, false) => frontmatter::compare(a, b),
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
@@ -0,0 +1,739 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::cmp::Ordering;
+use serde::Serialize;
+use serde_yaml::Value;
+use thiserror::Error;
+
+use crate::frontmatter;
+use crate::fs::MarkdownFile;
+use crate::search::{self, QueryTerm, SearchIndex};
+use crate::tags;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Errors in the search query syntax
+#[derive(Debug, Error)]
+pub enum QueryError {
+    #[error("Unbalanced parentheses in query")]
+    UnbalancedParentheses,
+    #[error("Unterminated quote in query")]
+    UnterminatedQuote,
+    #[error("Unterminated '[' in query")]
+    UnterminatedBracket,
+    #[error("Invalid property filter '[{0}]', expected [property], [property:value] or [property:>value]")]
+    InvalidProperty(String),
+    #[error("Expected a search term after '{0}'")]
+    ExpectedTerm(&'static str),
+    #[error("Expected a search term before '{0}'")]
+    ExpectedTermBefore(&'static str),
+    #[error("Invalid date '{0}', expected YYYY, YYYY-MM or YYYY-MM-DD")]
+    InvalidDate(String),
+}
+
+/// A parsed search query such as
+/// `tag:rust -tag:draft category:projects modified:>2026-01-01 "exact phrase" OR foo`.
+///
+/// Terms next to each other must all match; `OR` binds looser than that and
+/// parentheses group. `-term` or `NOT term` negates. Supported fields are
+/// `tag:` and `category:` (where `tag:area/*` and `category:work/*` include
+/// nested ones), `name:` and `modified:`; frontmatter properties are
+/// matched with `[status]`, `[status:active]` or `[due:<2026-06-01]`.
+/// Anything else is full-text.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// `None` matches every note
+    expr: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Text(QueryTerm),
+    /// A tag, and whether tags nested under it match too
+    Tag(String, bool),
+    /// A category, and whether categories nested in it match too
+    Category(String, bool),
+    Name(String),
+    Modified(DateRange),
+    Property(PropertyFilter),
+}
+
+/// A half-open range of unix timestamps; open ends are unbounded
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DateRange {
+    start: Option<u64>,
+    end: Option<u64>,
+}
+
+/// A condition on a frontmatter property; without a comparison the property
+/// only has to be set
+#[derive(Debug, Clone, PartialEq)]
+struct PropertyFilter {
+    key: String,
+    condition: Option<(Comparison, Value)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A note matching a query, with its relevance if the query contained text
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryMatch {
+    #[serde(flatten)]
+    pub file: MarkdownFile,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+    /// HTML excerpt with matches wrapped in `<mark>` tags
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+impl Query {
+    /// Parse a query string. An empty query matches every note.
+    pub fn parse(input: &str) -> Result<Self, QueryError> {
+        let tokens = lex(input)?;
+        if tokens.is_empty() {
+            return Ok(Self::default());
+        }
+        
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_or(None)?;
+        if parser.pos < parser.tokens.len() {
+            return Err(QueryError::UnbalancedParentheses);
+        }
+        
+        Ok(Self { expr: Some(expr) })
+    }
+    
+    /// Additionally require notes to carry the given tag, or with
+    /// `include_nested` the tag or one nested under it
+    pub fn require_tag(&mut self, tag: &str, include_nested: bool) {
+        self.require(Expr::Tag(tag.trim_start_matches('#').to_string(), include_nested));
+    }
+    
+    /// Additionally require notes to be in the given category, or with
+    /// `include_nested` in it or one of its subcategories
+    pub fn require_category(&mut self, category: &str, include_nested: bool) {
+        self.require(Expr::Category(category.trim_matches('/').to_string(), include_nested));
+    }
+    
+    fn require(&mut self, expr: Expr) {
+        self.expr = Some(match self.expr.take() {
+            Some(existing) => Expr::And(vec![existing, expr]),
+            None => expr,
+        });
+    }
+    
+    /// Evaluate the query against notes, looking up text terms in the search index.
+    ///
+    /// Results are ordered by relevance when the query contains text terms,
+    /// and by name otherwise.
+    pub fn evaluate<'a>(
+        &self,
+        notes: impl Iterator<Item = &'a MarkdownFile>,
+        search: &SearchIndex,
+    ) -> Vec<QueryMatch> {
+        let Some(expr) = &self.expr else {
+            let mut matches: Vec<QueryMatch> = notes
+                .map(|file| QueryMatch { file: file.clone(), score: None, snippet: None })
+                .collect();
+            matches.sort_by(|a, b| a.file.name.cmp(&b.file.name));
+            return matches;
+        };
+        
+        // Look up every text term in the full-text index once
+        let mut text_terms = Vec::new();
+        expr.collect_text_terms(false, &mut text_terms);
+        let text_scores: HashMap<&QueryTerm, HashMap<PathBuf, f32>> = text_terms.iter()
+            .map(|(term, _)| (*term, search.matches(term)))
+            .collect();
+        
+        // Only terms the note has to contain count towards relevance
+        let positive_terms: Vec<QueryTerm> = text_terms.iter()
+            .filter(|(_, negated)| !negated)
+            .map(|(term, _)| (*term).clone())
+            .collect();
+        
+        let mut matches: Vec<QueryMatch> = notes
+            .filter(|file| expr.matches(file, &text_scores))
+            .map(|file| {
+                if positive_terms.is_empty() {
+                    return QueryMatch { file: file.clone(), score: None, snippet: None };
+                }
+                let score = positive_terms.iter()
+                    .filter_map(|term| text_scores.get(term)?.get(&file.path))
+                    .sum();
+                QueryMatch {
+                    file: file.clone(),
+                    score: Some(score),
+                    snippet: search.snippet(&file.path, &positive_terms),
+                }
+            })
+            .collect();
+        
+        matches.sort_by(|a, b| {
+            let by_score = b.score.unwrap_or(0.0).total_cmp(&a.score.unwrap_or(0.0));
+            by_score.then_with(|| a.file.name.cmp(&b.file.name))
+        });
+        matches
+    }
+}
+
+impl Expr {
+    fn matches(&self, file: &MarkdownFile, text_scores: &HashMap<&QueryTerm, HashMap<PathBuf, f32>>) -> bool {
+        match self {
+            Expr::And(exprs) => exprs.iter().all(|e| e.matches(file, text_scores)),
+            Expr::Or(exprs) => exprs.iter().any(|e| e.matches(file, text_scores)),
+            Expr::Not(expr) => !expr.matches(file, text_scores),
+            Expr::Text(term) => text_scores.get(term).is_some_and(|docs| docs.contains_key(&file.path)),
+            Expr::Tag(tag, false) => file.tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase()),
+            Expr::Tag(tag, true) => file.tags.iter().any(|t| tags::is_within(t, tag)),
+            Expr::Category(category, false) => file.category.as_deref()
+                .is_some_and(|c| c.replace('\\', "/").to_lowercase() == category.to_lowercase()),
+            // Categories nest like tags do, with `/` between the levels
+            Expr::Category(category, true) => file.category.as_deref()
+                .is_some_and(|c| tags::is_within(&c.replace('\\', "/"), category)),
+            Expr::Name(name) => file.name.to_lowercase().contains(&name.to_lowercase()),
+            Expr::Modified(range) => file.modified.is_some_and(|m| range.contains(m)),
+            Expr::Property(filter) => filter.matches(file),
+        }
+    }
+    
+    /// Gather all text terms, noting whether each sits below a negation
+    fn collect_text_terms<'a>(&'a self, negated: bool, terms: &mut Vec<(&'a QueryTerm, bool)>) {
+        match self {
+            Expr::And(exprs) | Expr::Or(exprs) => {
+                for expr in exprs {
+                    expr.collect_text_terms(negated, terms);
+                }
+            },
+            Expr::Not(expr) => expr.collect_text_terms(!negated, terms),
+            Expr::Text(term) => terms.push((term, negated)),
+            _ => {},
+        }
+    }
+}
+
+impl PropertyFilter {
+    fn matches(&self, file: &MarkdownFile) -> bool {
+        let Some(value) = file.properties.get(self.key.as_str()) else {
+            return false;
+        };
+        let Some((comparison, expected)) = &self.condition else {
+            return !value.is_null();
+        };
+        
+        // A list matches if any of its items does
+        let values: Vec<&Value> = match value {
+            Value::Sequence(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        values.into_iter().any(|value| comparison.holds(frontmatter::compare(value, expected)))
+    }
+}
+
+impl Comparison {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl DateRange {
+    fn contains(&self, timestamp: u64) -> bool {
+        self.start.is_none_or(|start| timestamp >= start) && self.end.is_none_or(|end| timestamp < end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Or,
+    And,
+    Not,
+    /// The inside of `[...]`
+    Property(String),
+    Term {
+        field: Option<String>,
+        value: String,
+        quoted: bool,
+    },
+}
+
+/// Fields that can be used as `field:value`
+const FIELDS: [&str; 4] = ["tag", "category", "name", "modified"];
+
+fn lex(input: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            },
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            },
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            },
+            '[' => {
+                chars.next();
+                let mut filter = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        closed = true;
+                        break;
+                    }
+                    filter.push(c);
+                }
+                if !closed {
+                    return Err(QueryError::UnterminatedBracket);
+                }
+                tokens.push(Token::Property(filter));
+            },
+            '-' => {
+                chars.next();
+                // A lone dash is just punctuation
+                if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                    tokens.push(Token::Not);
+                }
+            },
+            _ => {
+                let mut word = String::new();
+                let mut quoted = false;
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    if c == '"' {
+                        // Quoted section, possibly after `field:`
+                        quoted = true;
+                        let mut closed = false;
+                        for c in chars.by_ref() {
+                            if c == '"' {
+                                closed = true;
+                                break;
+                            }
+                            word.push(c);
+                        }
+                        if !closed {
+                            return Err(QueryError::UnterminatedQuote);
+                        }
+                    } else {
+                        word.push(c);
+                    }
+                }
+                
+                tokens.push(match word.as_str() {
+                    "OR" if !quoted => Token::Or,
+                    "AND" if !quoted => Token::And,
+                    "NOT" if !quoted => Token::Not,
+                    _ => split_field(word, quoted),
+                });
+            }
+        }
+    }
+    
+    Ok(tokens)
+}
+
+/// Turn `field:value` into a field term when the field is known
+fn split_field(word: String, quoted: bool) -> Token {
+    if let Some((field, value)) = word.split_once(':') {
+        let field = field.to_lowercase();
+        if FIELDS.contains(&field.as_str()) && !value.is_empty() {
+            return Token::Term {
+                field: Some(field),
+                value: value.to_string(),
+                quoted,
+            };
+        }
+    }
+    
+    Token::Term { field: None, value: word, quoted }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    
+    /// `and_expr ("OR" and_expr)*`
+    fn parse_or(&mut self, after: Option<&'static str>) -> Result<Expr, QueryError> {
+        let mut branches = vec![self.parse_and(after)?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            branches.push(self.parse_and(Some("OR"))?);
+        }
+        
+        Ok(if branches.len() == 1 { branches.remove(0) } else { Expr::Or(branches) })
+    }
+    
+    /// `unary (("AND")? unary)*`
+    fn parse_and(&mut self, after: Option<&'static str>) -> Result<Expr, QueryError> {
+        let mut terms = Vec::new();
+        // The operator the next term is expected to follow, if any
+        let mut pending = after;
+        loop {
+            match self.peek() {
+                None | Some(Token::RParen) | Some(Token::Or) => break,
+                Some(Token::And) if terms.is_empty() => return Err(QueryError::ExpectedTermBefore("AND")),
+                Some(Token::And) => {
+                    self.pos += 1;
+                    pending = Some("AND");
+                },
+                _ => {
+                    terms.push(self.parse_unary()?);
+                    pending = None;
+                },
+            }
+        }
+        
+        if terms.is_empty() {
+            return Err(match (pending, self.peek()) {
+                (Some(op), _) => QueryError::ExpectedTerm(op),
+                (None, Some(Token::Or)) => QueryError::ExpectedTermBefore("OR"),
+                _ => QueryError::UnbalancedParentheses,
+            });
+        }
+        if let Some(op) = pending {
+            if op == "AND" {
+                return Err(QueryError::ExpectedTerm(op));
+            }
+        }
+        
+        Ok(if terms.len() == 1 { terms.remove(0) } else { Expr::And(terms) })
+    }
+    
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        let token = self.tokens[self.pos].clone();
+        self.pos += 1;
+        
+        match token {
+            Token::Not => {
+                match self.peek() {
+                    None | Some(Token::RParen) | Some(Token::Or) | Some(Token::And) => {
+                        Err(QueryError::ExpectedTerm("NOT"))
+                    },
+                    _ => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+                }
+            },
+            Token::LParen => {
+                let expr = self.parse_or(Some("("))?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(QueryError::UnbalancedParentheses);
+                }
+                self.pos += 1;
+                Ok(expr)
+            },
+            Token::Term { field, value, quoted } => term_expr(field.as_deref(), &value, quoted),
+            Token::Property(filter) => property_expr(&filter),
+            Token::RParen | Token::Or | Token::And => Err(QueryError::UnbalancedParentheses),
+        }
+    }
+}
+
+fn term_expr(field: Option<&str>, value: &str, quoted: bool) -> Result<Expr, QueryError> {
+    Ok(match field {
+        Some("tag") => {
+            // `tag:area/*` matches `area` and every tag nested under it
+            let tag = value.trim_start_matches('#');
+            match tag.strip_suffix("/*") {
+                Some(parent) => Expr::Tag(parent.to_string(), true),
+                None => Expr::Tag(tag.to_string(), false),
+            }
+        },
+        Some("category") => {
+            // `category:work/*` matches `work` and every category inside it
+            match value.strip_suffix("/*") {
+                Some(parent) => Expr::Category(parent.to_string(), true),
+                None => Expr::Category(value.trim_end_matches('/').to_string(), false),
+            }
+        },
+        Some("name") => Expr::Name(value.to_string()),
+        Some("modified") => Expr::Modified(parse_date_range(value)?),
+        _ => {
+            let text = if quoted { format!("\"{}\"", value) } else { value.to_string() };
+            let mut terms: Vec<Expr> = search::parse_text_query(&text)
+                .into_iter()
+                .map(Expr::Text)
+                .collect();
+            // Punctuation-only words match everything, like an empty `AND`
+            if terms.len() == 1 { terms.remove(0) } else { Expr::And(terms) }
+        },
+    })
+}
+
+/// Parse the inside of `[property]` or `[property:value]`, where the value
+/// may start with `<`, `<=`, `>`, `>=` or `=`
+fn property_expr(filter: &str) -> Result<Expr, QueryError> {
+    let invalid = || QueryError::InvalidProperty(filter.to_string());
+    
+    let (key, condition) = match filter.split_once(':') {
+        Some((key, value)) => {
+            let (comparison, value) = [
+                ("<=", Comparison::LessOrEqual),
+                (">=", Comparison::GreaterOrEqual),
+                ("<", Comparison::Less),
+                (">", Comparison::Greater),
+                ("=", Comparison::Equal),
+            ].iter()
+                .find_map(|(op, comparison)| value.strip_prefix(op).map(|value| (*comparison, value)))
+                .unwrap_or((Comparison::Equal, value));
+            let value = value.trim().trim_matches('"');
+            if value.is_empty() {
+                return Err(invalid());
+            }
+            (key, Some((comparison, frontmatter::parse_value(value))))
+        },
+        None => (filter, None),
+    };
+    
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Expr::Property(PropertyFilter { key: key.to_string(), condition }))
+}
+
+/// Parse `>D`, `>=D`, `<D`, `<=D`, `=D`, `D` or `D1..D2`, where each date is
+/// a year, a month or a day
+fn parse_date_range(value: &str) -> Result<DateRange, QueryError> {
+    if let Some((from, to)) = value.split_once("..") {
+        let start = if from.is_empty() { None } else { Some(parse_period(from)?.0) };
+        let end = if to.is_empty() { None } else { Some(parse_period(to)?.1) };
+        return Ok(DateRange { start, end });
+    }
+    
+    let (op, date) = ["<=", ">=", "<", ">", "="].iter()
+        .find_map(|op| value.strip_prefix(op).map(|date| (*op, date)))
+        .unwrap_or(("=", value));
+    let (start, end) = parse_period(date)?;
+    
+    Ok(match op {
+        ">" => DateRange { start: Some(end), end: None },
+        ">=" => DateRange { start: Some(start), end: None },
+        "<" => DateRange { start: None, end: Some(start) },
+        "<=" => DateRange { start: None, end: Some(end) },
+        _ => DateRange { start: Some(start), end: Some(end) },
+    })
+}
+
+/// Parse a UTC year, month or day into the unix timestamps of its start and end
+fn parse_period(date: &str) -> Result<(u64, u64), QueryError> {
+    let invalid = || QueryError::InvalidDate(date.to_string());
+    
+    let parts: Vec<&str> = date.split('-').collect();
+    let numbers: Vec<i64> = parts.iter()
+        .map(|p| p.parse::<i64>().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    
+    // Notes can't have been modified outside these years, and limiting them
+    // keeps the date math below from overflowing
+    if !(1970..=9999).contains(&numbers[0]) {
+        return Err(invalid());
+    }
+    
+    let (start, end) = match numbers.as_slice() {
+        [year] => (days_from_civil(*year, 1, 1), days_from_civil(year + 1, 1, 1)),
+        [year, month] if (1..=12).contains(month) => {
+            let (next_year, next_month) = if *month == 12 { (year + 1, 1) } else { (*year, month + 1) };
+            (days_from_civil(*year, *month, 1), days_from_civil(next_year, next_month, 1))
+        },
+        [year, month, day] if (1..=12).contains(month) && *day >= 1 && *day <= days_in_month(*year, *month) => {
+            let start = days_from_civil(*year, *month, *day);
+            (start, start + 1)
+        },
+        _ => return Err(invalid()),
+    };
+    
+    let to_timestamp = |days: i64| (days * SECONDS_PER_DAY) as u64;
+    Ok((to_timestamp(start), to_timestamp(end)))
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Format a unix timestamp as a UTC `YYYY-MM-DD` date
+pub fn format_date(timestamp: u64) -> String {
+    // Inverse of `days_from_civil`
+    let days = (timestamp / SECONDS_PER_DAY as u64) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    days_from_civil(next_year, next_month, 1) - days_from_civil(year, month, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn term(value: &str) -> Token {
+        Token::Term { field: None, value: value.to_string(), quoted: false }
+    }
+    
+    fn word(word: &str) -> Expr {
+        Expr::Text(QueryTerm::Word(word.to_string()))
+    }
+    
+    fn parse(input: &str) -> Option<Expr> {
+        Query::parse(input).unwrap().expr
+    }
+    
+    #[test]
+    fn lexes_operators_fields_and_properties() {
+        let tokens = lex("tag:rust -draft OR (NOT foo AND [status:active])").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Term { field: Some("tag".to_string()), value: "rust".to_string(), quoted: false },
+            Token::Not,
+            term("draft"),
+            Token::Or,
+            Token::LParen,
+            Token::Not,
+            term("foo"),
+            Token::And,
+            Token::Property("status:active".to_string()),
+            Token::RParen,
+        ]);
+    }
+    
+    #[test]
+    fn lexes_quotes_and_unknown_fields() {
+        let tokens = lex(r#"name:"my note" "OR" http://example.com - x"#).unwrap();
+        assert_eq!(tokens, vec![
+            Token::Term { field: Some("name".to_string()), value: "my note".to_string(), quoted: true },
+            Token::Term { field: None, value: "OR".to_string(), quoted: true },
+            term("http://example.com"),
+            term("x"),
+        ]);
+    }
+    
+    #[test]
+    fn rejects_unterminated_quotes_and_brackets() {
+        assert!(matches!(lex(r#"say "hello"#), Err(QueryError::UnterminatedQuote)));
+        assert!(matches!(lex("[status"), Err(QueryError::UnterminatedBracket)));
+    }
+    
+    #[test]
+    fn or_binds_looser_than_and() {
+        assert_eq!(parse("a b OR c"), Some(Expr::Or(vec![
+            Expr::And(vec![word("a"), word("b")]),
+            word("c"),
+        ])));
+        assert_eq!(parse("a (b OR c)"), Some(Expr::And(vec![
+            word("a"),
+            Expr::Or(vec![word("b"), word("c")]),
+        ])));
+        assert_eq!(parse("a AND b"), parse("a b"));
+    }
+    
+    #[test]
+    fn parses_negation_and_fields() {
+        assert_eq!(parse("-tag:draft NOT category:work/*"), Some(Expr::And(vec![
+            Expr::Not(Box::new(Expr::Tag("draft".to_string(), false))),
+            Expr::Not(Box::new(Expr::Category("work".to_string(), true))),
+        ])));
+        assert_eq!(parse("tag:#area/*"), Some(Expr::Tag("area".to_string(), true)));
+        assert_eq!(parse("[due:<=2026-06-01]"), Some(Expr::Property(PropertyFilter {
+            key: "due".to_string(),
+            condition: Some((Comparison::LessOrEqual, frontmatter::parse_value("2026-06-01"))),
+        })));
+        assert_eq!(parse("  "), None);
+    }
+    
+    #[test]
+    fn rejects_misplaced_operators() {
+        assert!(matches!(Query::parse("a OR"), Err(QueryError::ExpectedTerm("OR"))));
+        assert!(matches!(Query::parse("a AND"), Err(QueryError::ExpectedTerm("AND"))));
+        assert!(matches!(Query::parse("AND a"), Err(QueryError::ExpectedTermBefore("AND"))));
+        assert!(matches!(Query::parse("OR a"), Err(QueryError::ExpectedTermBefore("OR"))));
+        assert!(matches!(Query::parse("a NOT"), Err(QueryError::ExpectedTerm("NOT"))));
+        assert!(matches!(Query::parse("(a"), Err(QueryError::UnbalancedParentheses)));
+        assert!(matches!(Query::parse("a)"), Err(QueryError::UnbalancedParentheses)));
+        assert!(matches!(Query::parse("[:x]"), Err(QueryError::InvalidProperty(_))));
+    }
+    
+    #[test]
+    fn parses_years_months_and_days() {
+        assert_eq!(parse_period("1970").unwrap(), (0, 31_536_000));
+        assert_eq!(parse_period("2026").unwrap(), (1_767_225_600, 1_798_761_600));
+        assert_eq!(parse_period("2026-02").unwrap(), (1_769_904_000, 1_772_323_200));
+        assert_eq!(parse_period("2026-12").unwrap().1, 1_798_761_600);
+        assert_eq!(parse_period("2024-02-29").unwrap(), (1_709_164_800, 1_709_251_200));
+    }
+    
+    #[test]
+    fn rejects_invalid_and_out_of_range_dates() {
+        for date in ["", "2026-13", "2026-00", "2025-02-29", "2026-04-31", "2026-01-01-01", "soon", "1969", "10000"] {
+            assert!(matches!(parse_period(date), Err(QueryError::InvalidDate(_))), "{:?}", date);
+        }
+        assert!(Query::parse("modified:100000000000000").is_err());
+        assert!(Query::parse("modified:9223372036854775807").is_err());
+        assert!(Query::parse("modified:2026-99999999999999999999").is_err());
+    }
+    
+    #[test]
+    fn parses_date_ranges() {
+        let (start, end) = parse_period("2026").unwrap();
+        assert_eq!(parse_date_range(">2026").unwrap(), DateRange { start: Some(end), end: None });
+        assert_eq!(parse_date_range(">=2026").unwrap(), DateRange { start: Some(start), end: None });
+        assert_eq!(parse_date_range("<2026").unwrap(), DateRange { start: None, end: Some(start) });
+        assert_eq!(parse_date_range("<=2026").unwrap(), DateRange { start: None, end: Some(end) });
+        assert_eq!(parse_date_range("2026").unwrap(), DateRange { start: Some(start), end: Some(end) });
+        
+        let range = parse_date_range("2026-01..2026-02").unwrap();
+        assert_eq!(range, DateRange { start: Some(start), end: Some(parse_period("2026-02").unwrap().1) });
+        assert_eq!(parse_date_range("..2026").unwrap(), DateRange { start: None, end: Some(end) });
+        assert!(range.contains(start) && !range.contains(parse_period("2026-03").unwrap().0));
+    }
+    
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1_709_164_800), "2024-02-29");
+        assert_eq!(format_date(parse_period("9999-12-31").unwrap().0), "9999-12-31");
+    }
+}
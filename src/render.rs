@@ -0,0 +1,237 @@
+use std::path::Path;
+use comrak::nodes::NodeValue;
+use comrak::{format_html, parse_document, Anchorizer, Arena, ComrakOptions};
+
+use crate::attachments;
+use crate::config::FormatsConfig;
+use crate::format;
+use crate::frontmatter;
+use crate::links::{self, LinkKind, LinkResolver};
+use crate::property_query::{PropertyQuery, QueryFormat, QueryResult};
+use crate::query;
+use crate::search::escape_html;
+
+/// Renders a note to HTML.
+///
+/// Uses GitHub Flavored Markdown with tables, task lists, footnotes,
+/// strikethrough and autolinks. Wiki links are turned into links that open
+/// the note in the app, images and links to other files in the vault are
+/// served from the assets endpoint, headings get anchors, and raw HTML as
+/// well as dangerous URLs are stripped from the output.
+///
+/// ` ```query ` code blocks are replaced by the list or table of notes they
+/// select, using `run_query` to evaluate them.
+pub fn render_markdown(
+    content: &str,
+    source: &Path,
+    resolver: &LinkResolver,
+    run_query: impl Fn(&PropertyQuery) -> QueryResult,
+) -> String {
+    let content = rewrite_wiki_links(content, source, resolver);
+    let options = options();
+    
+    let arena = Arena::new();
+    let root = parse_document(&arena, &content, &options);
+    for node in root.descendants() {
+        if let NodeValue::Link(link) | NodeValue::Image(link) = &mut node.data.borrow_mut().value {
+            if let Some(url) = asset_url(&link.url, source, resolver.formats()) {
+                link.url = url;
+            }
+        }
+    }
+    
+    let query_blocks: Vec<_> = root.descendants()
+        .filter(|node| matches!(&node.data.borrow().value, NodeValue::CodeBlock(block) if block.fenced && block.info.trim() == "query"))
+        .collect();
+    for node in query_blocks {
+        let markdown = match &node.data.borrow().value {
+            NodeValue::CodeBlock(block) => match PropertyQuery::parse(&block.literal) {
+                Ok(query) => query_markdown(&run_query(&query)),
+                Err(err) => format!("> **Query error:** {}\n", escape_markdown(&err.to_string())),
+            },
+            _ => continue,
+        };
+        // Put the rendered result in place of the code block
+        let rendered = parse_document(&arena, &markdown, &options);
+        for child in rendered.children().collect::<Vec<_>>() {
+            node.insert_before(child);
+        }
+        node.detach();
+    }
+    
+    let mut html = Vec::new();
+    if format_html(root, &options, &mut html).is_err() {
+        return String::new();
+    }
+    String::from_utf8_lossy(&html).into_owned()
+}
+
+/// Where a relative link to a file in the vault (other than a note) is served.
+///
+/// Returns `None` for links that should be left alone, such as web pages,
+/// anchors, links to notes and paths leaving the notes directory.
+fn asset_url(url: &str, source: &Path, formats: &FormatsConfig) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    if !links::is_local_path(path) || format::is_note_link(path, formats) {
+        return None;
+    }
+    
+    let path = links::percent_decode(path);
+    let path = match path.strip_prefix('/') {
+        Some(rooted) => Path::new(rooted).to_path_buf(),
+        None => source.parent().unwrap_or(Path::new("")).join(path),
+    };
+    let path = links::normalize(&path)?;
+    Some(attachments::asset_url(&path.to_string_lossy().replace('\\', "/")))
+}
+
+/// Renders a plain text or Org mode note to HTML, as preformatted text
+pub fn render_plain_text(content: &str) -> String {
+    format!("<pre class=\"plain-text\">{}</pre>", escape_html(content))
+}
+
+/// The markdown dialect notes are written in
+pub fn options() -> ComrakOptions {
+    let mut options = ComrakOptions::default();
+    
+    options.extension.strikethrough = true;
+    options.extension.tagfilter = true;
+    options.extension.table = true;
+    options.extension.autolink = true;
+    options.extension.tasklist = true;
+    options.extension.footnotes = true;
+    options.extension.header_ids = Some(String::new());
+    options.extension.front_matter_delimiter = Some("---".to_string());
+    
+    // Match the browser preview, which treats single newlines as line breaks
+    options.render.hardbreaks = true;
+    options.render.github_pre_lang = true;
+    // Never pass raw HTML or `javascript:` style URLs through
+    options.render.unsafe_ = false;
+    
+    options
+}
+
+/// Markdown showing the result of a query block: a list of links to the
+/// notes, or a table with a column per requested field
+fn query_markdown(result: &QueryResult) -> String {
+    if result.rows.is_empty() {
+        return "*No matching notes*\n".to_string();
+    }
+    
+    let cell = |column: &str, value: &serde_yaml::Value| {
+        let text = match (column, value.as_u64()) {
+            ("modified", Some(timestamp)) => query::format_date(timestamp),
+            _ => frontmatter::to_text(value),
+        };
+        escape_markdown(&text.replace(['\n', '\r'], " "))
+    };
+    
+    let mut markdown = String::new();
+    match result.format {
+        QueryFormat::List => {
+            for row in &result.rows {
+                markdown.push_str(&format!("- {}", note_link(&row.title, &row.path)));
+                let values: Vec<String> = result.columns.iter()
+                    .zip(&row.values)
+                    .filter(|(_, value)| !value.is_null())
+                    .map(|(column, value)| cell(column, value))
+                    .collect();
+                if !values.is_empty() {
+                    markdown.push_str(&format!(": {}", values.join(" · ")));
+                }
+                markdown.push('\n');
+            }
+        },
+        QueryFormat::Table => {
+            markdown.push_str("| Note |");
+            for column in &result.columns {
+                markdown.push_str(&format!(" {} |", escape_markdown(column)));
+            }
+            markdown.push_str("\n| --- |");
+            markdown.push_str(&" --- |".repeat(result.columns.len()));
+            markdown.push('\n');
+            for row in &result.rows {
+                markdown.push_str(&format!("| {} |", note_link(&row.title, &row.path)));
+                for (column, value) in result.columns.iter().zip(&row.values) {
+                    markdown.push_str(&format!(" {} |", cell(column, value)));
+                }
+                markdown.push('\n');
+            }
+        },
+    }
+    markdown
+}
+
+/// A markdown link that opens a note in the app
+fn note_link(label: &str, path: &Path) -> String {
+    let url = format!("/?note={}", encode_uri_component(&path.to_string_lossy().replace('\\', "/")));
+    format!("[{}]({})", escape_markdown(label), url)
+}
+
+/// Replace `[[Note#Heading|alias]]` with a regular markdown link to the note
+/// in the app. Links to notes that don't exist become plain text.
+fn rewrite_wiki_links(content: &str, source: &Path, resolver: &LinkResolver) -> String {
+    let mut rewritten = String::with_capacity(content.len());
+    let mut last = 0;
+    
+    for link in links::parse_links(content, resolver.formats()) {
+        if link.kind != LinkKind::Wiki {
+            continue;
+        }
+        
+        // Embeds (`![[Note]]`) are shown as plain links as well
+        let mut start = link.range.start;
+        if content[..start].ends_with('!') {
+            start -= 1;
+        }
+        
+        let label = match (&link.alias, &link.heading) {
+            (Some(alias), _) => alias.clone(),
+            (None, Some(heading)) => format!("{} › {}", link.target, heading),
+            (None, None) => link.target.clone(),
+        };
+        let label = escape_markdown(&label);
+        
+        rewritten.push_str(&content[last..start]);
+        match resolver.resolve(source, &link) {
+            Some(path) => {
+                let mut url = format!("/?note={}", encode_uri_component(&path.to_string_lossy().replace('\\', "/")));
+                if let Some(heading) = &link.heading {
+                    url.push('#');
+                    url.push_str(&Anchorizer::new().anchorize(heading.clone()));
+                }
+                rewritten.push_str(&format!("[{}]({})", label, url));
+            },
+            None => rewritten.push_str(&label),
+        }
+        last = link.range.end;
+    }
+    
+    rewritten.push_str(&content[last..]);
+    rewritten
+}
+
+/// Backslash-escape markdown punctuation so link labels render literally
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Percent-encode everything except unreserved URL characters
+fn encode_uri_component(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use anyhow::{Result, Context};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{DirEntry, Match, WalkBuilder};
+use tracing::warn;
+
+use crate::attachments::ATTACHMENTS_DIR;
+use crate::config::{FormatsConfig, ScanConfig};
+use crate::format;
+use crate::fs::DATA_DIR;
+
+/// File listing paths that mdlib should skip, written like a `.gitignore`
+pub const IGNORE_FILE: &str = ".mdlibignore";
+
+/// Decides which files and directories in the notes directory belong to the vault.
+///
+/// Skipped are hidden files and directories (unless enabled in the config),
+/// anything matched by a `.mdlibignore` or `.gitignore` file, and whatever
+/// the configured include and exclude globs leave out. mdlib's own data
+/// directory, `.git` and the `attachments` directories holding uploads are
+/// always skipped, so an upload is never mistaken for a note.
+pub struct VaultScanner {
+    base_dir: PathBuf,
+    /// The base directory with symlinks resolved, to keep the walk inside it
+    canonical_base: PathBuf,
+    config: ScanConfig,
+    /// Extensions of note files
+    formats: FormatsConfig,
+    /// Include globs as they are, exclude globs negated
+    globs: Override,
+    ignore_rules: IgnoreRules,
+}
+
+impl VaultScanner {
+    pub fn new(base_dir: &Path, config: ScanConfig, formats: FormatsConfig) -> Result<Self> {
+        let mut builder = OverrideBuilder::new(base_dir);
+        for glob in &config.include {
+            builder.add(glob).with_context(|| format!("Invalid include glob '{}'", glob))?;
+        }
+        for glob in &config.exclude {
+            builder.add(&format!("!{}", glob)).with_context(|| format!("Invalid exclude glob '{}'", glob))?;
+        }
+        let globs = builder.build().context("Failed to build scan globs")?;
+        let canonical_base = base_dir.canonicalize()
+            .with_context(|| format!("Failed to resolve notes directory {:?}", base_dir))?;
+        let ignore_rules = IgnoreRules {
+            base_dir: base_dir.to_path_buf(),
+            file_names: if config.gitignore { &[".gitignore", IGNORE_FILE] } else { &[IGNORE_FILE] },
+            cache: Arc::default(),
+        };
+        
+        Ok(Self {
+            base_dir: base_dir.to_path_buf(),
+            canonical_base,
+            config,
+            formats,
+            globs,
+            ignore_rules,
+        })
+    }
+    
+    /// The notes below `dir`
+    pub fn note_files(&self, dir: &Path) -> Vec<PathBuf> {
+        self.walk(dir)
+            .map(DirEntry::into_path)
+            .filter(|path| path.is_file() && self.is_note_file(path))
+            .collect()
+    }
+    
+    /// Whether a path names a note by its extension, ignored or not
+    pub fn is_note_file(&self, path: &Path) -> bool {
+        format::is_note_file(path, &self.formats)
+    }
+    
+    /// Extensions of note files
+    pub fn formats(&self) -> &FormatsConfig {
+        &self.formats
+    }
+    
+    /// The directories below `dir`, not including `dir` itself
+    pub fn directories(&self, dir: &Path) -> Vec<PathBuf> {
+        self.walk(dir)
+            .filter(|entry| entry.depth() > 0)
+            .map(DirEntry::into_path)
+            .filter(|path| path.is_dir())
+            .collect()
+    }
+    
+    /// Whether a path (absolute or relative to the base directory) is skipped,
+    /// either itself or because one of its parent directories is. The path
+    /// doesn't need to exist.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let abs_path = self.base_dir.join(path);
+        let Ok(rel_path) = abs_path.strip_prefix(&self.base_dir) else {
+            return true;
+        };
+        
+        let skipped_name = rel_path.components()
+            .any(|component| is_always_skipped(component.as_os_str()) || (!self.config.hidden && is_hidden(component.as_os_str())));
+        let in_attachments = rel_path.parent()
+            .is_some_and(|parent| parent.components().any(|component| component.as_os_str() == ATTACHMENTS_DIR));
+        if skipped_name || in_attachments || (is_dir && rel_path.file_name() == Some(OsStr::new(ATTACHMENTS_DIR))) {
+            return true;
+        }
+        
+        // Globs are checked against every directory on the way, like the walk does
+        let parent_ignored = abs_path.ancestors()
+            .skip(1)
+            .take_while(|dir| *dir != self.base_dir)
+            .any(|dir| self.globs.matched(dir, true).is_ignore());
+        if parent_ignored || self.globs.matched(&abs_path, is_dir).is_ignore() {
+            return true;
+        }
+        
+        self.ignore_rules.is_ignored(&abs_path, is_dir)
+    }
+    
+    /// Drop the cached rules of the ignore files in `dir` (absolute or relative
+    /// to the base directory), so they are read again when next needed
+    pub fn forget_ignore_rules(&self, dir: &Path) {
+        self.ignore_rules.forget(&self.base_dir.join(dir));
+    }
+    
+    // Helper function to walk a directory, following symlinks that stay inside
+    // the vault and skipping ignored entries
+    fn walk(&self, dir: &Path) -> impl Iterator<Item = DirEntry> {
+        let globs = self.globs.clone();
+        let canonical_base = self.canonical_base.clone();
+        let ignore_rules = self.ignore_rules.clone();
+        
+        // Ignore files are applied by the filter rather than the walk itself, so
+        // the walk and `is_ignored` share their cached rules
+        let mut builder = WalkBuilder::new(dir);
+        builder
+            .follow_links(true)
+            .hidden(!self.config.hidden)
+            .parents(false)
+            .git_ignore(false)
+            .git_global(false)
+            .git_exclude(false)
+            .ignore(false)
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|file_type| file_type.is_dir());
+                if entry.depth() == 0 {
+                    return true;
+                }
+                if is_always_skipped(entry.file_name()) || globs.matched(entry.path(), is_dir).is_ignore() {
+                    return false;
+                }
+                if is_dir && entry.file_name() == ATTACHMENTS_DIR {
+                    return false;
+                }
+                if ignore_rules.is_ignored(entry.path(), is_dir) {
+                    return false;
+                }
+                
+                // Same containment rule as resolve_vault_path: whatever a path
+                // resolves to has to be inside the vault, which rules out
+                // symlinks leading out of it and dangling ones
+                match entry.path().canonicalize() {
+                    Ok(target) => target.starts_with(&canonical_base),
+                    Err(_) => false,
+                }
+            });
+        
+        builder.build().filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            // Symlink loops end up here too, instead of being walked forever
+            Err(err) => {
+                warn!("Skipping part of the notes directory: {}", err);
+                None
+            },
+        })
+    }
+}
+
+/// The rules from the ignore files in each directory of the vault, read when
+/// first needed and dropped when one of the files changes. Clones share the cache.
+#[derive(Clone)]
+struct IgnoreRules {
+    base_dir: PathBuf,
+    /// Names of the ignore files that are read
+    file_names: &'static [&'static str],
+    cache: Arc<RwLock<HashMap<PathBuf, Arc<Gitignore>>>>,
+}
+
+impl IgnoreRules {
+    /// Whether the ignore files leave out an absolute path inside the vault.
+    /// Files closer to the path take precedence over those further up; those
+    /// outside the vault don't count.
+    fn is_ignored(&self, abs_path: &Path, is_dir: bool) -> bool {
+        for dir in abs_path.ancestors().skip(1).take_while(|dir| dir.starts_with(&self.base_dir)) {
+            match self.rules(dir).matched_path_or_any_parents(abs_path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {},
+            }
+        }
+        false
+    }
+    
+    fn forget(&self, dir: &Path) {
+        self.cache.write()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(dir);
+    }
+    
+    // Helper function to get the rules of the ignore files in a directory, reading them once
+    fn rules(&self, dir: &Path) -> Arc<Gitignore> {
+        if let Some(rules) = self.cache.read().unwrap_or_else(|err| err.into_inner()).get(dir) {
+            return rules.clone();
+        }
+        
+        let mut builder = GitignoreBuilder::new(dir);
+        for name in self.file_names {
+            let file = dir.join(name);
+            if file.is_file() {
+                if let Some(err) = builder.add(&file) {
+                    warn!("Failed to read {:?}: {}", file, err);
+                }
+            }
+        }
+        let rules = Arc::new(builder.build().unwrap_or_else(|err| {
+            warn!("Invalid ignore rules in {:?}: {}", dir, err);
+            Gitignore::empty()
+        }));
+        self.cache.write()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(dir.to_path_buf(), rules.clone());
+        rules
+    }
+}
+
+/// Whether a file name names one of the ignore files
+pub fn is_ignore_file(name: &OsStr) -> bool {
+    name == IGNORE_FILE || name == ".gitignore"
+}
+
+fn is_always_skipped(name: &OsStr) -> bool {
+    name == DATA_DIR || name == ".git"
+}
+
+fn is_hidden(name: &OsStr) -> bool {
+    name.to_str().is_some_and(|name| name.starts_with('.') && name.len() > 1)
+}
@@ -0,0 +1,380 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::fs::MarkdownFile;
+use crate::format::NoteFormat;
+use crate::frontmatter;
+
+/// BM25 term frequency saturation
+const K1: f32 = 1.2;
+/// BM25 document length normalization
+const B: f32 = 0.75;
+/// Upper bound on the number of terms a single prefix query expands to
+const MAX_PREFIX_EXPANSIONS: usize = 64;
+/// Bytes of context shown before the first match in a snippet
+const SNIPPET_CONTEXT_BEFORE: usize = 60;
+/// Maximum snippet length in bytes
+const SNIPPET_LENGTH: usize = 200;
+
+/// The parts of a note that are indexed separately
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Title = 0,
+    Headings = 1,
+    Body = 2,
+    Frontmatter = 3,
+}
+
+const FIELD_COUNT: usize = 4;
+const FIELDS: [Field; FIELD_COUNT] = [Field::Title, Field::Headings, Field::Body, Field::Frontmatter];
+
+impl Field {
+    /// How much a match in this field counts towards the score
+    fn boost(self) -> f32 {
+        match self {
+            Field::Title => 3.0,
+            Field::Headings => 2.0,
+            Field::Body => 1.0,
+            Field::Frontmatter => 1.5,
+        }
+    }
+}
+
+/// A word in a field, with its byte range in the field text
+#[derive(Debug)]
+struct Token {
+    term: String,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, Default)]
+struct FieldText {
+    text: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug)]
+struct Document {
+    fields: [FieldText; FIELD_COUNT],
+}
+
+/// Inverted full-text index over the notes, ranked with BM25F
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    documents: HashMap<PathBuf, Document>,
+    /// Term -> document -> term frequency per field
+    postings: BTreeMap<String, HashMap<PathBuf, [u32; FIELD_COUNT]>>,
+    /// Sum of token counts per field, for average field lengths
+    total_lengths: [usize; FIELD_COUNT],
+}
+
+/// One part of a parsed text query
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum QueryTerm {
+    /// A single word
+    Word(String),
+    /// Any word starting with the given prefix (`word*`)
+    Prefix(String),
+    /// Words that must appear next to each other (`"some phrase"`)
+    Phrase(Vec<String>),
+}
+
+impl SearchIndex {
+    /// Add or replace a note in the index
+    pub fn insert(&mut self, file: &MarkdownFile, content: &str) {
+        self.remove(&file.path);
+        
+        let document = build_document(file, content);
+        for (i, field) in document.fields.iter().enumerate() {
+            self.total_lengths[i] += field.tokens.len();
+            for token in &field.tokens {
+                let frequencies = self.postings.entry(token.term.clone())
+                    .or_default()
+                    .entry(file.path.clone())
+                    .or_insert([0; FIELD_COUNT]);
+                frequencies[i] += 1;
+            }
+        }
+        
+        self.documents.insert(file.path.clone(), document);
+    }
+    
+    /// Remove a note from the index
+    pub fn remove(&mut self, path: &Path) {
+        let Some(document) = self.documents.remove(path) else {
+            return;
+        };
+        
+        for (i, field) in document.fields.iter().enumerate() {
+            self.total_lengths[i] -= field.tokens.len();
+            for token in &field.tokens {
+                if let Some(docs) = self.postings.get_mut(&token.term) {
+                    docs.remove(path);
+                    if docs.is_empty() {
+                        self.postings.remove(&token.term);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Score every note matching a single query term
+    pub fn matches(&self, term: &QueryTerm) -> HashMap<PathBuf, f32> {
+        let scores = match term {
+            QueryTerm::Word(word) => self.score_term(word),
+            QueryTerm::Prefix(prefix) => {
+                let mut combined: HashMap<&PathBuf, f32> = HashMap::new();
+                for word in self.expand_prefix(prefix) {
+                    for (path, score) in self.score_term(word) {
+                        *combined.entry(path).or_default() += score;
+                    }
+                }
+                combined
+            },
+            QueryTerm::Phrase(words) => self.score_phrase(words),
+        };
+        
+        scores.into_iter()
+            .map(|(path, score)| (path.clone(), score))
+            .collect()
+    }
+    
+    /// Build an HTML excerpt of a note with the given query terms highlighted
+    pub fn snippet(&self, path: &Path, terms: &[QueryTerm]) -> Option<String> {
+        let mut words = HashSet::new();
+        let mut prefixes = Vec::new();
+        for term in terms {
+            match term {
+                QueryTerm::Word(word) => {
+                    words.insert(word.clone());
+                },
+                QueryTerm::Prefix(prefix) => prefixes.push(prefix.clone()),
+                QueryTerm::Phrase(phrase) => words.extend(phrase.iter().cloned()),
+            }
+        }
+        
+        build_snippet(self.documents.get(path)?, &words, &prefixes)
+    }
+    
+    /// BM25F score of a single term for every document containing it
+    fn score_term(&self, term: &str) -> HashMap<&PathBuf, f32> {
+        let Some(docs) = self.postings.get(term) else {
+            return HashMap::new();
+        };
+        
+        let doc_count = self.documents.len() as f32;
+        let idf = ((doc_count - docs.len() as f32 + 0.5) / (docs.len() as f32 + 0.5) + 1.0).ln();
+        
+        docs.iter()
+            .filter_map(|(path, frequencies)| {
+                let document = self.documents.get(path)?;
+                let weighted_tf: f32 = FIELDS.iter()
+                    .map(|&field| {
+                        let i = field as usize;
+                        let length = document.fields[i].tokens.len() as f32;
+                        let average = (self.total_lengths[i] as f32 / doc_count).max(1.0);
+                        let norm = 1.0 - B + B * length / average;
+                        field.boost() * frequencies[i] as f32 / norm
+                    })
+                    .sum();
+                Some((path, idf * weighted_tf * (K1 + 1.0) / (weighted_tf + K1)))
+            })
+            .collect()
+    }
+    
+    /// Score documents in which the words occur consecutively in one field
+    fn score_phrase(&self, words: &[String]) -> HashMap<&PathBuf, f32> {
+        let mut scores: Option<HashMap<&PathBuf, f32>> = None;
+        for word in words {
+            let term_scores = self.score_term(word);
+            scores = Some(match scores {
+                None => term_scores,
+                Some(previous) => previous.into_iter()
+                    .filter_map(|(path, score)| term_scores.get(path).map(|s| (path, score + s)))
+                    .collect(),
+            });
+        }
+        
+        scores.unwrap_or_default()
+            .into_iter()
+            .filter(|(path, _)| {
+                self.documents.get(*path)
+                    .is_some_and(|doc| doc.fields.iter().any(|field| contains_phrase(&field.tokens, words)))
+            })
+            .collect()
+    }
+    
+    /// Indexed terms starting with the given prefix
+    fn expand_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.postings.range::<str, _>((std::ops::Bound::Included(prefix), std::ops::Bound::Unbounded))
+            .map(|(term, _)| term)
+            .take_while(move |term| term.starts_with(prefix))
+            .take(MAX_PREFIX_EXPANSIONS)
+    }
+}
+
+/// Parse a text query into words, `prefix*` terms and `"quoted phrases"`
+pub fn parse_text_query(query: &str) -> Vec<QueryTerm> {
+    let mut terms = Vec::new();
+    
+    for (i, part) in query.split('"').enumerate() {
+        if i % 2 == 1 {
+            // Inside quotes
+            let words: Vec<String> = tokenize(part).into_iter().map(|t| t.term).collect();
+            match words.len() {
+                0 => {},
+                1 => terms.push(QueryTerm::Word(words.into_iter().next().unwrap_or_default())),
+                _ => terms.push(QueryTerm::Phrase(words)),
+            }
+            continue;
+        }
+        
+        for word in part.split_whitespace() {
+            let is_prefix = word.ends_with('*');
+            let tokens = tokenize(word);
+            let count = tokens.len();
+            for (j, token) in tokens.into_iter().enumerate() {
+                // Only the last piece of `foo-bar*` is a prefix
+                if is_prefix && j + 1 == count {
+                    terms.push(QueryTerm::Prefix(token.term));
+                } else {
+                    terms.push(QueryTerm::Word(token.term));
+                }
+            }
+        }
+    }
+    
+    terms
+}
+
+/// Split text into lowercase alphanumeric words, remembering where they are
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    
+    for (i, c) in text.char_indices() {
+        if c.is_alphanumeric() {
+            start.get_or_insert(i);
+        } else if let Some(s) = start.take() {
+            tokens.push(Token { term: text[s..i].to_lowercase(), start: s, end: i });
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(Token { term: text[s..].to_lowercase(), start: s, end: text.len() });
+    }
+    
+    tokens
+}
+
+/// Split a note into the fields that are indexed separately
+fn build_document(file: &MarkdownFile, content: &str) -> Document {
+    let format = NoteFormat::of(&file.path);
+    let (frontmatter, body) = frontmatter::split(content, format);
+    
+    let stem = file.path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("");
+    let title = match &file.title {
+        Some(title) => format!("{}\n{}", title, stem),
+        None => stem.to_string(),
+    };
+    
+    // Headings are indexed on their own; everything else is body text
+    let mut headings = String::new();
+    let mut text = String::new();
+    let mut in_code_block = false;
+    let heading_marker = match format {
+        NoteFormat::Markdown => Some('#'),
+        NoteFormat::Org => Some('*'),
+        NoteFormat::PlainText => None,
+    };
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        if format == NoteFormat::Markdown && (trimmed.starts_with("```") || trimmed.starts_with("~~~")) {
+            in_code_block = !in_code_block;
+        }
+        let heading = heading_marker
+            .filter(|&marker| !in_code_block && trimmed.starts_with(marker) && trimmed.trim_start_matches(marker).starts_with(' '));
+        if let Some(marker) = heading {
+            headings.push_str(trimmed.trim_start_matches(marker).trim());
+            headings.push('\n');
+        } else {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+    
+    let field = |text: String| FieldText { tokens: tokenize(&text), text };
+    Document {
+        fields: [
+            field(title),
+            field(headings),
+            field(text),
+            field(frontmatter.unwrap_or("").to_string()),
+        ],
+    }
+}
+
+fn contains_phrase(tokens: &[Token], words: &[String]) -> bool {
+    tokens.windows(words.len())
+        .any(|window| window.iter().zip(words).all(|(token, word)| token.term == *word))
+}
+
+/// Build an HTML excerpt around the first match, preferring the body text
+fn build_snippet(document: &Document, terms: &HashSet<String>, prefixes: &[String]) -> Option<String> {
+    let is_match = |term: &str| terms.contains(term) || prefixes.iter().any(|p| term.starts_with(p.as_str()));
+    
+    let order = [Field::Body, Field::Headings, Field::Frontmatter, Field::Title];
+    let field = order.iter()
+        .map(|&f| &document.fields[f as usize])
+        .find(|field| field.tokens.iter().any(|t| is_match(&t.term)))?;
+    let first = field.tokens.iter().find(|t| is_match(&t.term))?;
+    
+    // Pick a window of characters around the first match
+    let text = &field.text;
+    let start = floor_char_boundary(text, first.start.saturating_sub(SNIPPET_CONTEXT_BEFORE));
+    let word_start = text[start..first.start].char_indices()
+        .find(|(_, c)| c.is_whitespace())
+        .map(|(offset, c)| start + offset + c.len_utf8());
+    let start = match word_start {
+        // Don't start in the middle of a word
+        Some(word_start) if start > 0 => word_start,
+        _ => start,
+    };
+    let end = floor_char_boundary(text, (start + SNIPPET_LENGTH).min(text.len()));
+    
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut cursor = start;
+    for token in field.tokens.iter().filter(|t| t.start >= start && t.end <= end && is_match(&t.term)) {
+        snippet.push_str(&escape_html(&text[cursor..token.start]));
+        snippet.push_str("<mark>");
+        snippet.push_str(&escape_html(&text[token.start..token.end]));
+        snippet.push_str("</mark>");
+        cursor = token.end;
+    }
+    snippet.push_str(&escape_html(&text[cursor..end]));
+    if end < text.len() {
+        snippet.push('…');
+    }
+    
+    Some(snippet.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while index > 0 && !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Escape text for use in HTML
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
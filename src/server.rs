@@ -1,21 +1,37 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::fs as std_fs;
-use tokio::sync::RwLock;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
 use axum::{
-    extract::{Path as AxumPath, State, Query},
-    response::IntoResponse,
-    routing::{get, post, put, delete},
-    Router, Json, http::StatusCode,
+    extract::{multipart::{Field, MultipartError}, DefaultBodyLimit, Multipart, Path as AxumPath, State, Query},
+    middleware,
+    response::{IntoResponse, Response, sse::{Event, KeepAlive, Sse}},
+    routing::{get, post, put, patch, delete},
+    Router, Json, http::{header, HeaderMap, HeaderValue, StatusCode},
 };
 use serde::{Deserialize, Serialize};
 use anyhow::{Result, Context};
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::fs;
 use crate::config::AppConfig;
 use crate::embedded::static_handler;
+use crate::watcher::{self, FileEvent};
+use crate::index::NoteIndex;
+use crate::query;
+use crate::property_query::PropertyQuery;
+use crate::links::{self, LinkPlan, LinkUpdate};
+use crate::history::{self, DiffMode, History, SnapshotReason};
+use crate::git::GitRepo;
+use crate::trash::Trash;
+use crate::scan::VaultScanner;
+use crate::attachments;
+use crate::frontmatter;
+use crate::format::{self, NoteFormat};
+use crate::tags::{self, TagRename};
 
 // Define API types
 #[derive(Debug, Serialize)]
@@ -61,6 +77,31 @@ impl<T: Serialize> IntoResponse for ApiResult<T> {
     }
 }
 
+// Returned when a note changed on the server since the client loaded it
+#[derive(Debug, Serialize)]
+struct ApiConflict {
+    status: String,
+    message: String,
+    data: SaveConflict,
+}
+
+#[derive(Debug, Serialize)]
+struct SaveConflict {
+    /// What is currently on disk
+    content: String,
+    etag: String,
+    /// The client's edits merged with the current content, if they don't overlap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    merged: Option<String>,
+}
+
+/// The part of the configuration the browser needs
+#[derive(Debug, Serialize)]
+struct ClientConfig {
+    /// Extensions of note files, without the dot
+    extensions: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct CreateFileRequest {
     name: String,
@@ -70,13 +111,22 @@ struct CreateFileRequest {
 #[derive(Debug, Deserialize)]
 struct UpdateFileRequest {
     content: String,
+    /// The content the client's edits are based on, used to merge concurrent changes
+    #[serde(default)]
+    base: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct SearchQuery {
     q: Option<String>,
     tag: Option<String>,
+    /// Also match tags nested under `tag`
+    #[serde(default)]
+    subtags: bool,
     category: Option<String>,
+    /// Also match notes in categories nested in `category`
+    #[serde(default)]
+    subcategories: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -84,21 +134,163 @@ struct AddTagsRequest {
     tags: Vec<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct RenameTagsRequest {
+    /// Tags to rename; renaming several tags merges them into one
+    from: Vec<String>,
+    to: String,
+    /// Only report what would change, without changing anything
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct RenameTagsResult {
+    files: Vec<RenamedTags>,
+    /// Notes whose tags couldn't be renamed; the others are renamed regardless
+    failed: Vec<RenameTagsFailure>,
+    dry_run: bool,
+}
+
+/// The tags renamed in a single note
+#[derive(Debug, Serialize)]
+struct RenamedTags {
+    path: String,
+    changes: Vec<tags::TagChange>,
+}
+
+#[derive(Debug, Serialize)]
+struct RenameTagsFailure {
+    path: String,
+    error: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct CreateCategoryRequest {
     name: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct MoveCategoryRequest {
+    path: String,
+    /// The full new path, e.g. `archive/2025/acme` to move `work/acme` into `archive/2025`
+    new_path: String,
+    /// Rewrite links to and from the notes in the category
+    #[serde(default = "default_true")]
+    update_links: bool,
+    /// Only report what would change, without moving anything
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct MoveCategoryResult {
+    path: String,
+    /// Notes whose `category` frontmatter was (or, for a dry run, would be)
+    /// changed to the new category
+    updated_notes: Vec<String>,
+    /// Notes whose links were (or would be) rewritten
+    updated_links: Vec<LinkUpdate>,
+    /// Notes whose links couldn't be rewritten after the category was moved
+    failed_links: Vec<LinkUpdateFailure>,
+    dry_run: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteCategoryQuery {
+    /// Also delete a category that isn't empty, with everything in it
+    #[serde(default)]
+    recursive: bool,
+}
+
 #[derive(Debug, Deserialize)]
 struct RemoveTagsRequest {
     tags: Vec<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct MoveFileRequest {
+    path: String,
+    new_path: String,
+    /// Rewrite links in other notes that point at the moved note
+    #[serde(default = "default_true")]
+    update_links: bool,
+    /// Only report what would change, without moving anything
+    #[serde(default)]
+    dry_run: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize)]
+struct MoveFileResult {
+    /// New path of the note relative to the base directory
+    path: String,
+    /// Notes whose links were (or, for a dry run, would be) rewritten
+    updated_links: Vec<LinkUpdate>,
+    /// Notes whose links couldn't be rewritten after the note was moved
+    failed_links: Vec<LinkUpdateFailure>,
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct LinkUpdateFailure {
+    path: String,
+    error: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PropertyQueryRequest {
+    /// A query in the same syntax as ` ```query ` blocks
+    query: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RenderRequest {
+    content: String,
+    /// Path of the note the content belongs to, used to resolve links
+    path: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RenderedNote {
+    html: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryDiffQuery {
+    /// Revision to compare from
+    from: String,
+    /// Revision to compare to; the current content if omitted
+    to: Option<String>,
+    #[serde(default)]
+    mode: DiffMode,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestoreRevisionRequest {
+    revision: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLogQuery {
+    /// Maximum number of commits to return
+    limit: Option<usize>,
+}
+
 // App state
 #[derive(Clone)]
 struct AppState {
     base_dir: PathBuf,
     config: Arc<RwLock<AppConfig>>,
+    index: Arc<NoteIndex>,
+    events: broadcast::Sender<FileEvent>,
+    history: Arc<History>,
+    /// The git repository the notes directory is in, if any
+    git: Option<Arc<GitRepo>>,
+    trash: Arc<Trash>,
 }
 
 /// Start the web server
@@ -119,24 +311,113 @@ pub async fn start_server(base_dir: PathBuf, custom_config_path: Option<PathBuf>
     let config = AppConfig::load_or_default(&config_path)
         .context("Failed to load configuration")?;
     
+    let watch_files = config.watch_files;
+    // Bound request bodies by the file size limit. Notes are sent as JSON strings,
+    // where escaping can double their size, and uploads come with other form
+    // fields, so leave room for both; handlers check the exact size themselves.
+    let body_limit = usize::try_from(config.max_file_size())
+        .unwrap_or(usize::MAX)
+        .saturating_mul(2)
+        .saturating_add(1024 * 1024);
+    let (events, _) = broadcast::channel(100);
+    
+    // Work with an absolute base directory so paths from handlers and the watcher line up
+    let base_dir = base_dir.canonicalize()
+        .context(format!("Failed to open notes directory: {:?}", base_dir))?;
+    
+    // Index all notes once up front; handlers and the watcher keep it current
+    let scanner = VaultScanner::new(&base_dir, config.scan.clone(), config.formats.clone())
+        .context("Invalid scan settings")?;
+    let index = NoteIndex::build(&base_dir, config.max_file_size(), config.tags.clone(), scanner);
+    
+    let history = History::new(&base_dir, config.history.clone(), config.max_file_size());
+    
+    let trash = Trash::new(&base_dir, config.trash.clone());
+    
+    let git = GitRepo::open(&base_dir, config.git.clone());
+    if let Some(git) = &git {
+        if git.auto_commit() {
+            println!("📦 Committing changes to git");
+        }
+    }
+    
     let app_state = AppState {
         base_dir,
         config: Arc::new(RwLock::new(config)),
+        index: Arc::new(index),
+        events,
+        history: Arc::new(history),
+        git: git.map(Arc::new),
+        trash: Arc::new(trash),
+    };
+    
+    // Permanently delete old items from the trash, now and then every hour
+    let trash = app_state.trash.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            trash.purge_expired();
+        }
+    });
+    
+    // Watch the notes directory for changes made outside the app.
+    // The watcher stops when it is dropped, so keep it alive while serving.
+    let _watcher = if watch_files {
+        match watcher::start_watcher(&app_state.base_dir, app_state.index.clone(), app_state.events.clone()) {
+            Ok(watcher) => {
+                println!("👀 Watching for file changes");
+                Some(watcher)
+            },
+            Err(err) => {
+                warn!("Failed to start file watcher: {:#}", err);
+                None
+            }
+        }
+    } else {
+        None
     };
     
     // Define routes
     let api_routes = Router::new()
+        .route("/config", get(get_client_config))
         .route("/files", get(list_files))
         .route("/files", post(create_file))
         .route("/files/:filename", get(get_file))
         .route("/files/:filename", put(update_file))
         .route("/files/:filename", delete(delete_file))
+        .route("/move", post(move_file))
         .route("/search", get(search_files))
+        .route("/query", post(run_property_query))
+        .route("/attachments", post(upload_attachment))
+        .route("/assets/*path", get(get_asset))
+        .route("/render", post(render_markdown))
+        .route("/render/:filename", get(render_file))
+        .route("/history/:filename", get(list_history))
+        .route("/history/:filename/diff", get(diff_history))
+        .route("/history/:filename/restore", post(restore_revision))
+        .route("/git/log/:filename", get(git_log))
+        .route("/git/blame/:filename", get(git_blame))
+        .route("/links/:filename", get(get_links))
+        .route("/backlinks/:filename", get(get_backlinks))
+        .route("/properties/:filename", get(get_properties))
+        .route("/properties/:filename", patch(update_properties))
+        .route("/tags", get(list_tags))
+        .route("/tags/rename", post(rename_tags))
         .route("/tags/:filename", put(add_tags))
         .route("/tags/:filename", delete(remove_tags))
         .route("/category", post(create_category))
         .route("/category/:category_name", delete(delete_category))
-        .route("/categories", get(list_categories));
+        .route("/category/move", post(move_category))
+        .route("/categories", get(list_categories))
+        .route("/categories/tree", get(category_tree))
+        .route("/trash", get(list_trash))
+        .route("/trash", delete(empty_trash))
+        .route("/trash/:id", delete(purge_from_trash))
+        .route("/trash/:id/restore", post(restore_from_trash))
+        .route("/events", get(file_events))
+        .layer(middleware::map_response_with_state(app_state.clone(), payload_too_large_response))
+        .layer(DefaultBodyLimit::max(body_limit));
     
     // Combine API routes with static files
     // Use embedded static files instead of physical directory
@@ -170,80 +451,43 @@ pub async fn start_server(base_dir: PathBuf, custom_config_path: Option<PathBuf>
         .context("Server error")
 }
 
+/// Settings the browser needs, such as which extensions notes have
+async fn get_client_config(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let config = state.config.read().await;
+    ApiResult::Success(StatusCode::OK, ClientConfig {
+        extensions: config.formats.extensions.iter()
+            .map(|ext| ext.trim_start_matches('.').to_lowercase())
+            .collect(),
+    })
+}
+
 /// List all markdown files
 async fn list_files(
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    match fs::list_markdown_files(&state.base_dir) {
-        Ok(files) => ApiResult::Success(StatusCode::OK, files),
-        Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-    }
+    ApiResult::Success(StatusCode::OK, state.index.files())
 }
 
-/// Get the content of a file
+/// Get file content, with its ETag for conditional updates
 async fn get_file(
     State(state): State<AppState>,
     AxumPath(filename): AxumPath<String>,
-) -> impl IntoResponse {
-    // First, try to find the file directly at the path given
-    let direct_path = state.base_dir.join(&filename);
-    
-    // Check if file exists and is a markdown file
-    if direct_path.is_file() {
-        // Verify it's a markdown file or at least has no extension (like README)
-        if let Some(ext) = direct_path.extension() {
-            if ext != "md" {
-                return ApiResult::Error(StatusCode::NOT_FOUND, "Not a markdown file".to_string());
-            }
-        }
-        
-        return match fs::read_markdown_file(&direct_path) {
-            Ok(content) => ApiResult::Success(StatusCode::OK, content),
-            Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-        };
-    }
-    
-    // If the direct path doesn't work, the file might be in a category folder
-    // First, try to handle it as a path with slashes or backslashes
-    let path_parts: Vec<&str> = filename.split(['/', '\\']).collect();
-    if path_parts.len() > 1 {
-        let combined_path = state.base_dir.join(&filename);
-        if combined_path.is_file() {
-            return match fs::read_markdown_file(&combined_path) {
-                Ok(content) => ApiResult::Success(StatusCode::OK, content),
-                Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            };
-        }
-    }
-    
-    // Last resort: search for the file by name in all categories
-    // First, get a list of all markdown files
-    let all_files = match fs::list_markdown_files(&state.base_dir) {
-        Ok(files) => files,
-        Err(err) => return ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+) -> Response {
+    let path = match find_markdown_file(&state, &filename) {
+        Ok(path) => path,
+        Err(err) => return ApiResult::<()>::Error(error_status(&err), err.to_string()).into_response(),
     };
     
-    // Try to find a file with a matching name
-    let file_name_buf = PathBuf::from(&filename);
-    let file_name = file_name_buf.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or(&filename);
-    
-    for file in all_files {
-        let curr_file_name = file.path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
-        
-        if curr_file_name == file_name || curr_file_name.to_lowercase() == file_name.to_lowercase() {
-            return match fs::read_markdown_file(&file.path) {
-                Ok(content) => ApiResult::Success(StatusCode::OK, content),
-                Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            };
-        }
+    let max_size = state.config.read().await.max_file_size();
+    match fs::read_markdown_file(&path, max_size) {
+        Ok(content) => {
+            let etag = fs::content_etag(&content);
+            ([(header::ETAG, etag)], ApiResult::Success(StatusCode::OK, content)).into_response()
+        },
+        Err(err) => ApiResult::<()>::Error(error_status(&err), err.to_string()).into_response(),
     }
-    
-    // If we get here, we couldn't find the file
-    ApiResult::Error(StatusCode::NOT_FOUND, "File not found".to_string())
 }
 
 /// Create a new markdown file
@@ -256,11 +500,23 @@ async fn create_file(
         return ApiResult::Error(StatusCode::BAD_REQUEST, "Filename cannot be empty".to_string());
     }
     
+    let (max_size, formats) = {
+        let config = state.config.read().await;
+        (config.max_file_size(), config.formats.clone())
+    };
+    if let Err(err) = fs::check_file_size(request.content.len() as u64, max_size) {
+        return ApiResult::Error(error_status(&err), err.to_string());
+    }
+    
     // Extract category from the frontmatter if it exists
     let mut category_path = PathBuf::new();
     
     // Check if content has frontmatter with a category
-    if let Some(category) = extract_category_from_content(&request.content) {
+    let file_name = format::note_file_name(name, format::default_extension(&formats), &formats);
+    let category = frontmatter::parse(&request.content, NoteFormat::of(Path::new(&file_name)))
+        .ok()
+        .and_then(|properties| frontmatter::get_string(&properties, "category"));
+    if let Some(category) = category {
         // Make sure the category directory exists
         match fs::create_category(&state.base_dir, &category) {
             Ok(path) => {
@@ -269,7 +525,7 @@ async fn create_file(
             },
             Err(err) => {
                 return ApiResult::Error(
-                    StatusCode::INTERNAL_SERVER_ERROR, 
+                    error_status(&err), 
                     format!("Failed to create category directory: {}", err)
                 );
             }
@@ -279,25 +535,28 @@ async fn create_file(
     // Create the file in the appropriate location
     let file_path = if category_path.as_os_str().is_empty() {
         // No category, create in base directory
-        match fs::create_markdown_file(&state.base_dir, name, &request.content) {
+        match fs::create_markdown_file(&state.base_dir, name, &request.content, &formats) {
             Ok(path) => path,
             Err(err) => {
-                return ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
+                return ApiResult::Error(error_status(&err), err.to_string());
             }
         }
     } else {
         // Create in category directory
-        match fs::create_markdown_file(&category_path, name, &request.content) {
+        match fs::create_markdown_file(&category_path, name, &request.content, &formats) {
             Ok(path) => path,
             Err(err) => {
-                return ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
+                return ApiResult::Error(error_status(&err), err.to_string());
             }
         }
     };
     
+    state.index.refresh(&file_path);
+    
     // Return the relative path
     match fs::get_relative_path(&state.base_dir, &file_path) {
         Ok(rel_path) => {
+            commit_changes(&state, &[&rel_path], &format!("Create {}", rel_path.display()));
             ApiResult::Success(StatusCode::CREATED, rel_path.to_string_lossy().into_owned())
         },
         Err(err) => {
@@ -306,224 +565,384 @@ async fn create_file(
     }
 }
 
-// Helper function to extract category from content
-fn extract_category_from_content(content: &str) -> Option<String> {
-    if let Some(frontmatter) = extract_frontmatter(content) {
-        for line in frontmatter.lines() {
-            let line = line.trim();
-            if let Some(stripped) = line.strip_prefix("category:") {
-                return Some(stripped.trim().to_string());
-            }
+/// Update a file.
+///
+/// With an `If-Match` header the update only succeeds if the note still has
+/// that ETag (412 otherwise); with a `base` in the body it only succeeds if
+/// the note still has that content (409 otherwise). On a mismatch the current
+/// content is returned, along with a three-way merge if the edits don't overlap.
+async fn update_file(
+    State(state): State<AppState>,
+    AxumPath(filename): AxumPath<String>,
+    headers: HeaderMap,
+    Json(request): Json<UpdateFileRequest>,
+) -> Response {
+    let path = match find_markdown_file(&state, &filename) {
+        Ok(path) => path,
+        Err(err) => return ApiResult::<()>::Error(error_status(&err), err.to_string()).into_response(),
+    };
+    
+    let max_size = state.config.read().await.max_file_size();
+    if let Err(err) = fs::check_file_size(request.content.len() as u64, max_size) {
+        return ApiResult::<()>::Error(error_status(&err), err.to_string()).into_response();
+    }
+    
+    // Hold the file's lock so nothing can change it between the check and the write
+    let written = fs::with_file_lock(&path, || {
+        let current = fs::read_markdown_file(&path, max_size)?;
+        let current_etag = fs::content_etag(&current);
+        
+        let conflict_status = match headers.get(header::IF_MATCH) {
+            Some(if_match) if !etag_matches(if_match, &current_etag) => Some(StatusCode::PRECONDITION_FAILED),
+            Some(_) => None,
+            None => match &request.base {
+                Some(base) if *base != current => Some(StatusCode::CONFLICT),
+                _ => None,
+            },
+        };
+        if let Some(status) = conflict_status {
+            let merged = request.base.as_deref()
+                .and_then(|base| diffy::merge(base, &current, &request.content).ok());
+            return Ok(Err((status, SaveConflict {
+                content: current,
+                etag: current_etag,
+                merged,
+            })));
         }
+        
+        state.history.snapshot(&path, SnapshotReason::Update)?;
+        fs::write_markdown_file(&path, &request.content)?;
+        Ok(Ok(()))
+    });
+    match written {
+        Ok(Err((status, conflict))) => conflict_response(status, conflict),
+        Ok(Ok(())) => {
+            state.index.refresh(&path);
+            commit_changes(&state, &[&path], &format!("Update {}", note_label(&state, &path)));
+            let etag = fs::content_etag(&request.content);
+            ([(header::ETAG, etag)], ApiResult::Success(StatusCode::OK, "File updated".to_string())).into_response()
+        },
+        Err(err) => ApiResult::<()>::Error(error_status(&err), err.to_string()).into_response(),
     }
-    None
 }
 
-// Extract YAML frontmatter from markdown content if present
-fn extract_frontmatter(content: &str) -> Option<String> {
-    let trimmed = content.trim_start();
-    if let Some(stripped) = trimmed.strip_prefix("---") {
-        if let Some(end_index) = stripped.find("---") {
-            return Some(stripped[..end_index].trim().to_string());
-        }
+/// Delete a file
+async fn delete_file(
+    State(state): State<AppState>,
+    AxumPath(filename): AxumPath<String>,
+) -> impl IntoResponse {
+    let path = match find_markdown_file(&state, &filename) {
+        Ok(path) => path,
+        Err(err) => return ApiResult::Error(error_status(&err), err.to_string()),
+    };
+    
+    let deleted = fs::with_file_lock(&path, || {
+        state.history.snapshot(&path, SnapshotReason::Delete)?;
+        fs::delete_markdown_file(&path, &state.trash)
+    });
+    match deleted {
+        Ok(_) => {
+            state.index.refresh(&path);
+            commit_changes(&state, &[&path], &format!("Delete {}", note_label(&state, &path)));
+            ApiResult::Success(StatusCode::OK, "File moved to the trash".to_string())
+        },
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
     }
-    None
 }
 
-/// Update an existing file
-async fn update_file(
+/// Search for files matching a query, best matches first
+///
+/// `q` uses the query language in `query.rs`; the `tag` and `category`
+/// parameters are additionally required when given.
+async fn search_files(
+    State(state): State<AppState>,
+    Query(params): Query<SearchQuery>,
+) -> impl IntoResponse {
+    let mut search = match query::Query::parse(params.q.as_deref().unwrap_or("")) {
+        Ok(search) => search,
+        Err(err) => return ApiResult::Error(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+    if let Some(tag) = params.tag.as_deref().filter(|t| !t.is_empty()) {
+        search.require_tag(tag, params.subtags);
+    }
+    if let Some(category) = params.category.as_deref().filter(|c| !c.is_empty()) {
+        search.require_category(category, params.subcategories);
+    }
+    
+    ApiResult::Success(StatusCode::OK, state.index.query(&search))
+}
+
+/// Run a query over the notes and their frontmatter, as used in ` ```query ` blocks
+async fn run_property_query(
+    State(state): State<AppState>,
+    Json(request): Json<PropertyQueryRequest>,
+) -> impl IntoResponse {
+    match PropertyQuery::parse(&request.query) {
+        Ok(query) => ApiResult::Success(StatusCode::OK, state.index.property_query(&query)),
+        Err(err) => ApiResult::Error(StatusCode::BAD_REQUEST, err.to_string()),
+    }
+}
+
+/// Render a file to sanitized HTML
+async fn render_file(
     State(state): State<AppState>,
     AxumPath(filename): AxumPath<String>,
-    Json(request): Json<UpdateFileRequest>,
 ) -> impl IntoResponse {
-    // First, try direct path
-    let direct_path = state.base_dir.join(&filename);
+    let max_size = state.config.read().await.max_file_size();
+    let rendered = find_note_path(&state, &filename).and_then(|path| {
+        let content = fs::read_markdown_file(&state.base_dir.join(&path), max_size)?;
+        Ok(state.index.render(&path, &content))
+    });
     
-    if direct_path.is_file() {
-        // Verify it's a markdown file or README
-        let is_readme = direct_path.file_name()
-            .and_then(|name| name.to_str())
-            .map(|name| name.to_lowercase().starts_with("readme"))
-            .unwrap_or(false);
-            
-        if is_readme || direct_path.extension().is_some_and(|ext| ext == "md") {
-            return match fs::write_markdown_file(&direct_path, &request.content) {
-                Ok(_) => ApiResult::Success(StatusCode::OK, "File updated".to_string()),
-                Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            };
-        }
+    match rendered {
+        Ok(html) => ApiResult::Success(StatusCode::OK, RenderedNote { html }),
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
+    }
+}
+
+/// Render arbitrary markdown, such as unsaved editor content, to sanitized HTML
+async fn render_markdown(
+    State(state): State<AppState>,
+    Json(request): Json<RenderRequest>,
+) -> impl IntoResponse {
+    let max_size = state.config.read().await.max_file_size();
+    if let Err(err) = fs::check_file_size(request.content.len() as u64, max_size) {
+        return ApiResult::Error(error_status(&err), err.to_string());
     }
     
-    // If direct path doesn't work, the file might be in a category folder
-    // Try to handle it as a path with slashes or backslashes
-    let path_parts: Vec<&str> = filename.split(['/', '\\']).collect();
-    if path_parts.len() > 1 {
-        let combined_path = state.base_dir.join(&filename);
-        if combined_path.is_file() {
-            // Verify it's a markdown file
-            if combined_path.extension().is_some_and(|ext| ext == "md") {
-                return match fs::write_markdown_file(&combined_path, &request.content) {
-                    Ok(_) => ApiResult::Success(StatusCode::OK, "File updated".to_string()),
-                    Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-                };
-            }
+    let source = PathBuf::from(request.path.as_deref().unwrap_or("").trim_start_matches('/'));
+    let html = state.index.render(&source, &request.content);
+    ApiResult::Success(StatusCode::OK, RenderedNote { html })
+}
+
+/// Upload a file, such as an image pasted into a note.
+///
+/// Expects a multipart form with a `file` field, and optionally the `note`
+/// the file is for or a `category`. The file is stored in the `attachments`
+/// directory of that category, or of the notes directory, and the markdown
+/// to insert into the note is returned.
+async fn upload_attachment(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let max_file_size_mb = state.config.read().await.max_file_size_mb;
+    
+    let mut note = None;
+    let mut category = None;
+    let mut upload = None;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => return ApiResult::Error(err.status(), err.body_text()),
+        };
+        
+        let read = match field.name() {
+            Some("file") => {
+                let file_name = field.file_name().unwrap_or_default().to_string();
+                read_upload(field, max_file_size_mb).await.map(|data| upload = Some((file_name, data)))
+            },
+            Some("note") => field.text().await.map(|text| note = Some(text)).map_err(multipart_error),
+            Some("category") => field.text().await.map(|text| category = Some(text)).map_err(multipart_error),
+            _ => Ok(()),
+        };
+        if let Err((status, message)) = read {
+            return ApiResult::Error(status, message);
         }
     }
     
-    // Last resort: search for the file in all directories
-    // Get all markdown files
-    let all_files = match fs::list_markdown_files(&state.base_dir) {
-        Ok(files) => files,
-        Err(err) => return ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    let Some((file_name, data)) = upload else {
+        return ApiResult::Error(StatusCode::BAD_REQUEST, "No file was uploaded".to_string());
     };
     
-    // Try to find a file with a matching name
-    let file_name_buf = PathBuf::from(&filename);
-    let file_name = file_name_buf.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or(&filename);
+    // Attachments go next to the note they are for, or into the given category
+    let note_dir = match (note.as_deref().filter(|n| !n.is_empty()), category.as_deref().filter(|c| !c.trim().is_empty())) {
+        (Some(note), _) => find_note_path(&state, note)
+            .map(|path| path.parent().map(Path::to_path_buf).unwrap_or_default()),
+        (None, Some(category)) => fs::resolve_vault_path(&state.base_dir, category)
+            .map_err(anyhow::Error::from)
+            .and_then(|path| {
+                if !path.is_dir() {
+                    return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Category not found").into());
+                }
+                fs::get_relative_path(&state.base_dir, &path)
+            }),
+        (None, None) => Ok(PathBuf::new()),
+    };
     
-    for file in all_files {
-        let curr_file_name = file.path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
-        
-        if curr_file_name == file_name || curr_file_name.to_lowercase() == file_name.to_lowercase() {
-            return match fs::write_markdown_file(&file.path, &request.content) {
-                Ok(_) => ApiResult::Success(StatusCode::OK, "File updated".to_string()),
-                Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            };
-        }
+    let saved = note_dir.and_then(|dir| attachments::save_attachment(&state.base_dir, &dir, &file_name, &data));
+    match saved {
+        Ok(attachment) => {
+            commit_changes(&state, &[Path::new(&attachment.path)], &format!("Add attachment {}", attachment.path));
+            ApiResult::Success(StatusCode::CREATED, attachment)
+        },
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
+    }
+}
+
+/// Serve a file from the notes directory, such as an attachment
+async fn get_asset(
+    State(state): State<AppState>,
+    AxumPath(path): AxumPath<String>,
+) -> Response {
+    let path = path.trim_start_matches('/');
+    
+    // Never serve hidden files such as the contents of `.git`
+    if path.split(['/', '\\']).any(|part| part.starts_with('.') && part != "." && part != "..") {
+        return ApiResult::<()>::Error(StatusCode::NOT_FOUND, "File not found".to_string()).into_response();
     }
     
-    // If we get here, we couldn't find the file
-    ApiResult::Error(StatusCode::NOT_FOUND, "File not found".to_string())
+    let path = match fs::resolve_vault_path(&state.base_dir, path) {
+        Ok(path) if path.is_file() => path,
+        Ok(_) => return ApiResult::<()>::Error(StatusCode::NOT_FOUND, "File not found".to_string()).into_response(),
+        Err(err) => return ApiResult::<()>::Error(error_status(&err.into()), "Invalid path".to_string()).into_response(),
+    };
+    
+    match tokio::fs::read(&path).await {
+        Ok(data) => {
+            let mime_type = mime_guess::from_path(&path)
+                .first_or_octet_stream()
+                .as_ref()
+                .to_string();
+            (
+                [
+                    (header::CONTENT_TYPE, mime_type),
+                    (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+                    // Uploaded HTML or SVG files must not be able to run scripts on our origin
+                    (header::CONTENT_SECURITY_POLICY, "sandbox".to_string()),
+                ],
+                data,
+            ).into_response()
+        },
+        Err(err) => ApiResult::<()>::Error(error_status(&err.into()), "Failed to read file".to_string()).into_response(),
+    }
 }
 
-/// Delete a file
-async fn delete_file(
+/// List the stored revisions of a file, newest first
+async fn list_history(
     State(state): State<AppState>,
     AxumPath(filename): AxumPath<String>,
 ) -> impl IntoResponse {
-    // First, try direct path
-    let direct_path = state.base_dir.join(&filename);
+    let revisions = find_history_path(&state, &filename)
+        .and_then(|path| state.history.revisions(&path));
     
-    if direct_path.is_file() {
-        // Verify it's a markdown file or README
-        let is_readme = direct_path.file_name()
-            .and_then(|name| name.to_str())
-            .map(|name| name.to_lowercase().starts_with("readme"))
-            .unwrap_or(false);
-            
-        if is_readme || direct_path.extension().is_some_and(|ext| ext == "md") {
-            return match fs::delete_markdown_file(&direct_path) {
-                Ok(_) => ApiResult::Success(StatusCode::OK, "File deleted".to_string()),
-                Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            };
-        }
+    match revisions {
+        Ok(revisions) => ApiResult::Success(StatusCode::OK, revisions),
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
     }
+}
+
+/// Compare a revision of a file with another revision or the current content
+async fn diff_history(
+    State(state): State<AppState>,
+    AxumPath(filename): AxumPath<String>,
+    Query(query): Query<HistoryDiffQuery>,
+) -> impl IntoResponse {
+    let max_size = state.config.read().await.max_file_size();
+    let diff = find_history_path(&state, &filename).and_then(|path| {
+        let old = state.history.read(&path, &query.from)?;
+        let (new, new_label) = match &query.to {
+            Some(to) => (state.history.read(&path, to)?, to.as_str()),
+            None => (fs::read_markdown_file(&state.base_dir.join(&path), max_size)?, "current"),
+        };
+        Ok(history::diff(&old, &new, query.mode, &query.from, new_label))
+    });
     
-    // If direct path doesn't work, try handling it as a path with slashes or backslashes
-    let path_parts: Vec<&str> = filename.split(['/', '\\']).collect();
-    if path_parts.len() > 1 {
-        let combined_path = state.base_dir.join(&filename);
-        if combined_path.is_file() {
-            // Verify it's a markdown file
-            if combined_path.extension().is_some_and(|ext| ext == "md") {
-                return match fs::delete_markdown_file(&combined_path) {
-                    Ok(_) => ApiResult::Success(StatusCode::OK, "File deleted".to_string()),
-                    Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-                };
-            }
-        }
+    match diff {
+        Ok(diff) => ApiResult::Success(StatusCode::OK, diff),
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
     }
+}
+
+/// Restore a file to an earlier revision, recreating it if it was deleted
+async fn restore_revision(
+    State(state): State<AppState>,
+    AxumPath(filename): AxumPath<String>,
+    Json(request): Json<RestoreRevisionRequest>,
+) -> impl IntoResponse {
+    let restored = find_history_path(&state, &filename).and_then(|path| {
+        let content = state.history.read(&path, &request.revision)?;
+        let abs_path = state.base_dir.join(&path);
+        
+        fs::with_file_lock(&abs_path, || {
+            // The current content becomes a revision itself, so a restore can be undone
+            state.history.snapshot(&abs_path, SnapshotReason::Restore)?;
+            if let Some(parent) = abs_path.parent() {
+                std::fs::create_dir_all(parent).context("Failed to create category directory")?;
+            }
+            fs::write_markdown_file(&abs_path, &content)
+        })?;
+        Ok(abs_path)
+    });
     
-    // Last resort: search for the file in all directories
-    let all_files = match fs::list_markdown_files(&state.base_dir) {
-        Ok(files) => files,
-        Err(err) => return ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    match restored {
+        Ok(path) => {
+            state.index.refresh(&path);
+            let message = format!("Restore {} to revision {}", note_label(&state, &path), request.revision);
+            commit_changes(&state, &[&path], &message);
+            ApiResult::Success(StatusCode::OK, "Revision restored".to_string())
+        },
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
+    }
+}
+
+/// List the git commits that changed a file, newest first
+async fn git_log(
+    State(state): State<AppState>,
+    AxumPath(filename): AxumPath<String>,
+    Query(query): Query<GitLogQuery>,
+) -> impl IntoResponse {
+    let Some(git) = &state.git else {
+        return ApiResult::Error(StatusCode::NOT_FOUND, "The notes directory is not in a git repository".to_string());
     };
     
-    // Try to find a file with a matching name
-    let file_name_buf = PathBuf::from(&filename);
-    let file_name = file_name_buf.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or(&filename);
+    let log = find_history_path(&state, &filename)
+        .and_then(|path| git.log(&path, query.limit.unwrap_or(50)));
     
-    for file in all_files {
-        let curr_file_name = file.path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
-        
-        if curr_file_name == file_name || curr_file_name.to_lowercase() == file_name.to_lowercase() {
-            return match fs::delete_markdown_file(&file.path) {
-                Ok(_) => ApiResult::Success(StatusCode::OK, "File deleted".to_string()),
-                Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            };
-        }
+    match log {
+        Ok(commits) => ApiResult::Success(StatusCode::OK, commits),
+        Err(err) => ApiResult::Error(error_status(&err), format!("{:#}", err)),
     }
-    
-    // If we get here, we couldn't find the file
-    ApiResult::Error(StatusCode::NOT_FOUND, "File not found".to_string())
 }
 
-/// Search for files containing a query
-async fn search_files(
+/// Show which commit last changed each line of a file
+async fn git_blame(
     State(state): State<AppState>,
-    Query(query): Query<SearchQuery>,
+    AxumPath(filename): AxumPath<String>,
 ) -> impl IntoResponse {
-    // Get all markdown files
-    let files = match fs::list_markdown_files(&state.base_dir) {
-        Ok(files) => files,
-        Err(err) => {
-            return ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
-        }
+    let Some(git) = &state.git else {
+        return ApiResult::Error(StatusCode::NOT_FOUND, "The notes directory is not in a git repository".to_string());
     };
     
-    // Filter files by the search criteria
-    let mut matching_files = Vec::new();
+    let blame = find_note_path(&state, &filename)
+        .and_then(|path| git.blame(&path));
     
-    for file in files {
-        let mut matches = true;
-        
-        // Filter by text content if search term is provided
-        if let Some(term) = &query.q {
-            if !term.trim().is_empty() {
-                if let Ok(content) = fs::read_markdown_file(&file.path) {
-                    if !content.to_lowercase().contains(&term.to_lowercase()) {
-                        matches = false;
-                    }
-                } else {
-                    matches = false;
-                }
-            }
-        }
-        
-        // Filter by tag if provided
-        if let Some(tag) = &query.tag {
-            if !file.tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase()) {
-                matches = false;
-            }
-        }
-        
-        // Filter by category if provided
-        if let Some(category) = &query.category {
-            match &file.category {
-                Some(file_category) if file_category.to_lowercase() == category.to_lowercase() => {
-                    // Category matches
-                },
-                _ => {
-                    matches = false;
-                }
-            }
-        }
-        
-        if matches {
-            matching_files.push(file);
-        }
+    match blame {
+        Ok(lines) => ApiResult::Success(StatusCode::OK, lines),
+        Err(err) => ApiResult::Error(error_status(&err), format!("{:#}", err)),
+    }
+}
+
+/// Get the links from a file to other notes
+async fn get_links(
+    State(state): State<AppState>,
+    AxumPath(filename): AxumPath<String>,
+) -> impl IntoResponse {
+    match find_note_path(&state, &filename) {
+        Ok(path) => ApiResult::Success(StatusCode::OK, state.index.links(&path)),
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
+    }
+}
+
+/// Get the notes linking to a file, with the lines containing the links
+async fn get_backlinks(
+    State(state): State<AppState>,
+    AxumPath(filename): AxumPath<String>,
+) -> impl IntoResponse {
+    match find_note_path(&state, &filename) {
+        Ok(path) => ApiResult::Success(StatusCode::OK, state.index.backlinks(&path)),
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
     }
-    
-    ApiResult::Success(StatusCode::OK, matching_files)
 }
 
 /// Add tags to a file
@@ -532,66 +951,73 @@ async fn add_tags(
     AxumPath(filename): AxumPath<String>,
     Json(request): Json<AddTagsRequest>,
 ) -> impl IntoResponse {
-    // First, try direct path
-    let direct_path = state.base_dir.join(&filename);
+    let path = match find_markdown_file(&state, &filename) {
+        Ok(path) => path,
+        Err(err) => return ApiResult::Error(error_status(&err), err.to_string()),
+    };
     
-    if direct_path.is_file() {
-        // Verify it's a markdown file or README
-        let is_readme = direct_path.file_name()
-            .and_then(|name| name.to_str())
-            .map(|name| name.to_lowercase().starts_with("readme"))
-            .unwrap_or(false);
-            
-        if is_readme || direct_path.extension().is_some_and(|ext| ext == "md") {
-            return match fs::add_tags_to_file(&direct_path, &request.tags) {
-                Ok(_) => ApiResult::Success(StatusCode::OK, "Tags added".to_string()),
-                Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            };
-        }
+    let max_size = state.config.read().await.max_file_size();
+    let tagged = fs::add_tags_to_file(&path, &request.tags, max_size, || state.history.snapshot(&path, SnapshotReason::Update));
+    match tagged {
+        Ok(_) => {
+            state.index.refresh(&path);
+            let message = format!("Add tags {} to {}", request.tags.join(", "), note_label(&state, &path));
+            commit_changes(&state, &[&path], &message);
+            ApiResult::Success(StatusCode::OK, "Tags added".to_string())
+        },
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
     }
+}
+
+/// Get the frontmatter properties of a file
+async fn get_properties(
+    State(state): State<AppState>,
+    AxumPath(filename): AxumPath<String>,
+) -> impl IntoResponse {
+    let path = match find_markdown_file(&state, &filename) {
+        Ok(path) => path,
+        Err(err) => return ApiResult::Error(error_status(&err), err.to_string()),
+    };
     
-    // If direct path doesn't work, try handling it as a path with slashes or backslashes
-    let path_parts: Vec<&str> = filename.split(['/', '\\']).collect();
-    if path_parts.len() > 1 {
-        let combined_path = state.base_dir.join(&filename);
-        if combined_path.is_file() {
-            // Verify it's a markdown file
-            if combined_path.extension().is_some_and(|ext| ext == "md") {
-                return match fs::add_tags_to_file(&combined_path, &request.tags) {
-                    Ok(_) => ApiResult::Success(StatusCode::OK, "Tags added".to_string()),
-                    Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-                };
-            }
-        }
+    let max_size = state.config.read().await.max_file_size();
+    let properties = fs::read_markdown_file(&path, max_size)
+        .and_then(|content| Ok(frontmatter::parse(&content, NoteFormat::of(&path))?));
+    match properties {
+        Ok(properties) => ApiResult::Success(StatusCode::OK, properties),
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
     }
-    
-    // Last resort: search for the file in all directories
-    let all_files = match fs::list_markdown_files(&state.base_dir) {
-        Ok(files) => files,
-        Err(err) => return ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+}
+
+/// Update frontmatter properties of a file.
+///
+/// Takes a JSON object of properties to set; a `null` value removes the
+/// property. Properties that aren't mentioned are left as they are.
+async fn update_properties(
+    State(state): State<AppState>,
+    AxumPath(filename): AxumPath<String>,
+    Json(changes): Json<serde_json::Map<String, serde_json::Value>>,
+) -> impl IntoResponse {
+    let path = match find_markdown_file(&state, &filename) {
+        Ok(path) => path,
+        Err(err) => return ApiResult::Error(error_status(&err), err.to_string()),
     };
     
-    // Try to find a file with a matching name
-    let file_name_buf = PathBuf::from(&filename);
-    let file_name = file_name_buf.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or(&filename);
+    let changes = match serde_yaml::to_value(changes) {
+        Ok(serde_yaml::Value::Mapping(changes)) => changes,
+        _ => return ApiResult::Error(StatusCode::BAD_REQUEST, "Invalid properties".to_string()),
+    };
     
-    for file in all_files {
-        let curr_file_name = file.path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
-        
-        if curr_file_name == file_name || curr_file_name.to_lowercase() == file_name.to_lowercase() {
-            return match fs::add_tags_to_file(&file.path, &request.tags) {
-                Ok(_) => ApiResult::Success(StatusCode::OK, "Tags added".to_string()),
-                Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            };
-        }
+    let max_size = state.config.read().await.max_file_size();
+    let updated = fs::update_properties_of_file(&path, changes, max_size, || state.history.snapshot(&path, SnapshotReason::Update));
+    match updated {
+        Ok(properties) => {
+            state.index.refresh(&path);
+            let message = format!("Update properties of {}", note_label(&state, &path));
+            commit_changes(&state, &[&path], &message);
+            ApiResult::Success(StatusCode::OK, properties)
+        },
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
     }
-    
-    // If we get here, we couldn't find the file
-    ApiResult::Error(StatusCode::NOT_FOUND, "File not found".to_string())
 }
 
 /// Create a new category
@@ -607,58 +1033,100 @@ async fn create_category(
     
     // Create the category directory
     match fs::create_category(&state.base_dir, name) {
-        Ok(_) => ApiResult::Success(StatusCode::CREATED, name.to_string()),
-        Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+        Ok(path) => {
+            state.index.refresh(&path);
+            ApiResult::Success(StatusCode::CREATED, name.to_string())
+        },
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
     }
 }
 
-/// List all categories
-async fn list_categories(
+/// List all tags as a tree of nested tags, with the number of notes using each
+async fn list_tags(
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    let base_dir = &state.base_dir;
+    ApiResult::Success(StatusCode::OK, state.index.tag_tree())
+}
+
+/// Rename or merge tags across all notes, in frontmatter as well as hashtags.
+///
+/// Tags nested under a renamed tag are renamed along with it. A note that
+/// can't be updated doesn't stop the others; it is listed in `failed`.
+async fn rename_tags(
+    State(state): State<AppState>,
+    Json(request): Json<RenameTagsRequest>,
+) -> impl IntoResponse {
+    let (max_size, tags_config) = {
+        let config = state.config.read().await;
+        (config.max_file_size(), config.tags.clone())
+    };
     
-    // Get all markdown files to extract unique categories
-    let files = match fs::list_markdown_files(base_dir) {
-        Ok(files) => files,
-        Err(err) => {
-            return ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
-        }
+    let rename = TagRename {
+        from: request.from.iter()
+            .map(|tag| tag.trim().trim_start_matches('#').trim_matches('/').to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+        to: request.to.trim().trim_start_matches('#').trim_matches('/').to_string(),
     };
+    if rename.from.is_empty() {
+        return ApiResult::Error(StatusCode::BAD_REQUEST, "No tags to rename".to_string());
+    }
+    let valid = rename.to.chars().all(|c| tags::is_tag_char(c, &tags_config)) && !rename.to.contains("//");
+    if rename.to.is_empty() || !valid {
+        return ApiResult::Error(StatusCode::BAD_REQUEST, format!("Invalid tag name '{}'", request.to));
+    }
+    
+    let files: Vec<fs::MarkdownFile> = state.index.files().into_iter()
+        .filter(|file| file.tags.iter().any(|tag| rename.apply(tag).is_some()))
+        .collect();
     
-    // Extract unique categories from files
-    let mut categories = Vec::new();
+    let mut renamed = Vec::new();
+    let mut failed = Vec::new();
     for file in &files {
-        if let Some(category) = &file.category {
-            if !categories.contains(category) {
-                categories.push(category.clone());
-            }
+        let path = state.base_dir.join(&file.path);
+        let snapshot = || state.history.snapshot(&path, SnapshotReason::Update);
+        match fs::rename_tags_in_file(&path, &rename, &tags_config, max_size, request.dry_run, snapshot) {
+            Ok(changes) if changes.is_empty() => {},
+            Ok(changes) => {
+                if !request.dry_run {
+                    state.index.refresh(&path);
+                }
+                renamed.push(RenamedTags {
+                    path: file.path.to_string_lossy().into_owned(),
+                    changes,
+                });
+            },
+            Err(err) => {
+                warn!("Failed to rename tags in {:?}: {:#}", file.path, err);
+                failed.push(RenameTagsFailure {
+                    path: file.path.to_string_lossy().into_owned(),
+                    error: err.to_string(),
+                });
+            },
         }
     }
     
-    // Also scan for directories that might be empty categories
-    if let Ok(entries) = std_fs::read_dir(base_dir) {
-        for entry in entries.filter_map(|e| e.ok()) {
-            if let Ok(file_type) = entry.file_type() {
-                if file_type.is_dir() {
-                    if let Some(name) = entry.file_name().to_str() {
-                        // Skip hidden directories
-                        if !name.starts_with('.') {
-                            let category = name.to_string();
-                            if !categories.contains(&category) {
-                                categories.push(category);
-                            }
-                        }
-                    }
-                }
-            }
+    if !request.dry_run && !renamed.is_empty() {
+        let mut message = format!("Rename tags {} to {}\n\nUpdated tags in:\n", rename.from.join(", "), rename.to);
+        for file in &renamed {
+            message.push_str(&format!("- {}\n", file.path));
         }
+        let paths: Vec<&Path> = renamed.iter().map(|file| Path::new(&file.path)).collect();
+        commit_changes(&state, &paths, &message);
     }
     
-    // Sort categories
-    categories.sort();
-    
-    ApiResult::Success(StatusCode::OK, categories)
+    ApiResult::Success(StatusCode::OK, RenameTagsResult {
+        files: renamed,
+        failed,
+        dry_run: request.dry_run,
+    })
+}
+
+/// List all categories
+async fn list_categories(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    ApiResult::Success(StatusCode::OK, state.index.categories())
 }
 
 /// Remove tags from a file
@@ -667,72 +1135,116 @@ async fn remove_tags(
     AxumPath(filename): AxumPath<String>,
     Json(request): Json<RemoveTagsRequest>,
 ) -> impl IntoResponse {
-    // First, try direct path
-    let direct_path = state.base_dir.join(&filename);
+    let path = match find_markdown_file(&state, &filename) {
+        Ok(path) => path,
+        Err(err) => return ApiResult::Error(error_status(&err), err.to_string()),
+    };
     
-    if direct_path.is_file() {
-        // Verify it's a markdown file or README
-        let is_readme = direct_path.file_name()
-            .and_then(|name| name.to_str())
-            .map(|name| name.to_lowercase().starts_with("readme"))
-            .unwrap_or(false);
-            
-        if is_readme || direct_path.extension().is_some_and(|ext| ext == "md") {
-            return match fs::remove_tags_from_file(&direct_path, &request.tags) {
-                Ok(_) => ApiResult::Success(StatusCode::OK, "Tags removed".to_string()),
-                Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            };
-        }
+    let max_size = state.config.read().await.max_file_size();
+    let untagged = fs::remove_tags_from_file(&path, &request.tags, max_size, || state.history.snapshot(&path, SnapshotReason::Update));
+    match untagged {
+        Ok(_) => {
+            state.index.refresh(&path);
+            let message = format!("Remove tags {} from {}", request.tags.join(", "), note_label(&state, &path));
+            commit_changes(&state, &[&path], &message);
+            ApiResult::Success(StatusCode::OK, "Tags removed".to_string())
+        },
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
     }
+}
+
+/// List all categories as a tree of nested categories, with the number of notes in each
+async fn category_tree(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    ApiResult::Success(StatusCode::OK, state.index.category_tree())
+}
+
+/// Move or rename a category, along with its notes and subcategories.
+///
+/// Notes inside it with a `category` in their frontmatter are updated to the
+/// new category, and links to and from the moved notes are rewritten.
+async fn move_category(
+    State(state): State<AppState>,
+    Json(request): Json<MoveCategoryRequest>,
+) -> impl IntoResponse {
+    let paths = fs::move_category_target(&state.base_dir, &request.path, &request.new_path).and_then(|target| {
+        let source = fs::resolve_vault_path(&state.base_dir, &request.path)?;
+        Ok((fs::get_relative_path(&state.base_dir, &source)?, fs::get_relative_path(&state.base_dir, &target)?))
+    });
+    let (from, to) = match paths {
+        Ok(paths) => paths,
+        Err(err) => return ApiResult::Error(error_status(&err), err.to_string()),
+    };
     
-    // If direct path doesn't work, try handling it as a path with slashes or backslashes
-    let path_parts: Vec<&str> = filename.split(['/', '\\']).collect();
-    if path_parts.len() > 1 {
-        let combined_path = state.base_dir.join(&filename);
-        if combined_path.is_file() {
-            // Verify it's a markdown file
-            if combined_path.extension().is_some_and(|ext| ext == "md") {
-                return match fs::remove_tags_from_file(&combined_path, &request.tags) {
-                    Ok(_) => ApiResult::Success(StatusCode::OK, "Tags removed".to_string()),
-                    Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-                };
-            }
+    // Links are worked out against the index as it is before the move
+    let plan = request.update_links.then(|| state.index.plan_link_updates(&from, &to));
+    let mut updated_links = plan.as_ref().map(|plan| plan.updates.clone()).unwrap_or_default();
+    let mut failed_links = Vec::new();
+    
+    if !request.dry_run {
+        if let Err(err) = fs::move_category(&state.base_dir, &request.path, &request.new_path) {
+            return ApiResult::Error(error_status(&err), err.to_string());
+        }
+        state.index.refresh(&from);
+        state.index.refresh(&to);
+        if let Err(err) = state.history.rename(&from, &to) {
+            warn!("Failed to move history of {:?}: {:#}", from, err);
         }
     }
     
-    // Last resort: search for the file in all directories
-    let all_files = match fs::list_markdown_files(&state.base_dir) {
-        Ok(files) => files,
-        Err(err) => return ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-    };
-    
-    // Try to find a file with a matching name
-    let file_name_buf = PathBuf::from(&filename);
-    let file_name = file_name_buf.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or(&filename);
+    // Notes that name their category in the frontmatter should keep matching their folder
+    let max_size = state.config.read().await.max_file_size();
+    let (old_category, new_category) = (from.to_string_lossy().replace('\\', "/"), to.to_string_lossy().replace('\\', "/"));
+    let category_dir = if request.dry_run { &from } else { &to };
+    let mut updated = Vec::new();
+    for file in state.index.files().iter().filter(|file| file.path.starts_with(category_dir)) {
+        let path = state.base_dir.join(&file.path);
+        let snapshot = || state.history.snapshot(&path, SnapshotReason::Update);
+        match fs::update_category_in_file(&path, &old_category, &new_category, max_size, request.dry_run, snapshot) {
+            Ok(true) => {
+                if !request.dry_run {
+                    state.index.refresh(&path);
+                }
+                updated.push(links::moved_path(&file.path, &from, &to));
+            },
+            Ok(false) => {},
+            Err(err) => warn!("Failed to update the category of {:?}: {:#}", file.path, err),
+        }
+    }
     
-    for file in all_files {
-        let curr_file_name = file.path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
+    if !request.dry_run {
+        if let Some(plan) = &plan {
+            (updated_links, failed_links) = apply_link_updates(&state, plan, max_size);
+        }
         
-        if curr_file_name == file_name || curr_file_name.to_lowercase() == file_name.to_lowercase() {
-            return match fs::remove_tags_from_file(&file.path, &request.tags) {
-                Ok(_) => ApiResult::Success(StatusCode::OK, "Tags removed".to_string()),
-                Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            };
+        let mut message = format!("Move category {} to {}", from.display(), to.display());
+        if !updated_links.is_empty() {
+            message.push_str("\n\nUpdated links in:\n");
+            for update in &updated_links {
+                message.push_str(&format!("- {}\n", update.path.display()));
+            }
         }
+        let mut paths = vec![from.as_path(), to.as_path()];
+        paths.extend(updated_links.iter().map(|update| update.path.as_path()));
+        commit_changes(&state, &paths, &message);
     }
     
-    // If we get here, we couldn't find the file
-    ApiResult::Error(StatusCode::NOT_FOUND, "File not found".to_string())
+    ApiResult::Success(StatusCode::OK, MoveCategoryResult {
+        path: to.to_string_lossy().into_owned(),
+        updated_notes: updated.iter().map(|path| path.to_string_lossy().into_owned()).collect(),
+        updated_links,
+        failed_links,
+        dry_run: request.dry_run,
+    })
 }
 
-/// Delete a category 
+/// Delete a category by moving it to the trash; with `?recursive=true` this
+/// includes any notes and subcategories in it
 async fn delete_category(
     State(state): State<AppState>,
     AxumPath(category_name): AxumPath<String>,
+    Query(params): Query<DeleteCategoryQuery>,
 ) -> impl IntoResponse {
     // Validate the category name
     let name = category_name.trim();
@@ -741,8 +1253,331 @@ async fn delete_category(
     }
     
     // Check if the category exists and delete it
-    match fs::delete_category(&state.base_dir, name) {
-        Ok(_) => ApiResult::Success(StatusCode::OK, format!("Category '{}' deleted", name)),
-        Err(err) => ApiResult::Error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    match fs::delete_category(&state.base_dir, name, &state.trash, params.recursive) {
+        Ok(entry) => {
+            state.index.refresh(&entry.path);
+            commit_changes(&state, &[&entry.path], &format!("Delete category {}", entry.path.display()));
+            ApiResult::Success(StatusCode::OK, format!("Category '{}' moved to the trash", name))
+        },
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
+    }
+} 
+
+/// List deleted notes and categories, most recently deleted first
+async fn list_trash(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    match state.trash.entries() {
+        Ok(entries) => ApiResult::Success(StatusCode::OK, entries),
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
+    }
+}
+
+/// Put a deleted note or category back where it was
+async fn restore_from_trash(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> impl IntoResponse {
+    match state.trash.restore(&id) {
+        Ok(path) => {
+            // Refresh the whole top-level category, in case it had to be recreated
+            let top_level = path.iter().next().map(PathBuf::from).unwrap_or_default();
+            state.index.refresh(&top_level);
+            commit_changes(&state, &[&path], &format!("Restore {} from the trash", path.display()));
+            ApiResult::Success(StatusCode::OK, path.to_string_lossy().into_owned())
+        },
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
+    }
+}
+
+/// Permanently delete an item in the trash
+async fn purge_from_trash(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> impl IntoResponse {
+    match state.trash.purge(&id) {
+        Ok(_) => ApiResult::Success(StatusCode::OK, "Item permanently deleted".to_string()),
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
+    }
+}
+
+/// Permanently delete everything in the trash
+async fn empty_trash(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    match state.trash.purge_all() {
+        Ok(count) => ApiResult::Success(StatusCode::OK, format!("Permanently deleted {} item{}", count, if count == 1 { "" } else { "s" })),
+        Err(err) => ApiResult::Error(error_status(&err), err.to_string()),
+    }
+}
+
+/// Move or rename a file
+async fn move_file(
+    State(state): State<AppState>,
+    Json(request): Json<MoveFileRequest>,
+) -> impl IntoResponse {
+    let new_path = request.new_path.trim();
+    if new_path.is_empty() {
+        return ApiResult::Error(StatusCode::BAD_REQUEST, "Target path cannot be empty".to_string());
+    }
+    
+    // Locate the source file and work out where it goes
+    let source = match find_markdown_file(&state, &request.path) {
+        Ok(path) => path,
+        Err(err) => return ApiResult::Error(error_status(&err), err.to_string()),
+    };
+    let formats = state.config.read().await.formats.clone();
+    let paths = fs::move_target(&state.base_dir, &source, new_path, &formats).and_then(|target| {
+        Ok((fs::get_relative_path(&state.base_dir, &source)?, fs::get_relative_path(&state.base_dir, &target)?))
+    });
+    let (from, to) = match paths {
+        Ok(paths) => paths,
+        Err(err) => return ApiResult::Error(error_status(&err), err.to_string()),
+    };
+    
+    let plan = (request.update_links && from != to).then(|| state.index.plan_link_updates(&from, &to));
+    let mut updated_links = plan.as_ref().map(|plan| plan.updates.clone()).unwrap_or_default();
+    let mut failed_links = Vec::new();
+    
+    if !request.dry_run {
+        if let Err(err) = fs::move_markdown_file(&state.base_dir, &source, new_path, &formats) {
+            return ApiResult::Error(error_status(&err), err.to_string());
+        }
+        state.index.refresh(&from);
+        state.index.refresh(&to);
+        if let Err(err) = state.history.rename(&from, &to) {
+            warn!("Failed to move history of {:?}: {:#}", from, err);
+        }
+        
+        if let Some(plan) = &plan {
+            let max_size = state.config.read().await.max_file_size();
+            (updated_links, failed_links) = apply_link_updates(&state, plan, max_size);
+        }
+        
+        let mut message = format!("Move {} to {}", from.display(), to.display());
+        if !updated_links.is_empty() {
+            message.push_str("\n\nUpdated links in:\n");
+            for update in &updated_links {
+                message.push_str(&format!("- {}\n", update.path.display()));
+            }
+        }
+        let mut paths = vec![from.as_path(), to.as_path()];
+        paths.extend(updated_links.iter().map(|update| update.path.as_path()));
+        commit_changes(&state, &paths, &message);
+    }
+    
+    ApiResult::Success(StatusCode::OK, MoveFileResult {
+        path: to.to_string_lossy().into_owned(),
+        updated_links,
+        failed_links,
+        dry_run: request.dry_run,
+    })
+}
+
+// Helper function to rewrite links once the move is done. Each note is re-read
+// under its lock and rewritten again, so edits made since the plan are kept.
+fn apply_link_updates(state: &AppState, plan: &LinkPlan, max_size: u64) -> (Vec<LinkUpdate>, Vec<LinkUpdateFailure>) {
+    let rewriter = plan.rewriter();
+    let mut updated = Vec::new();
+    let mut failed = Vec::new();
+    for update in &plan.updates {
+        let path = state.base_dir.join(&update.path);
+        let written = fs::with_file_lock(&path, || {
+            let content = fs::read_markdown_file(&path, max_size)?;
+            let Some(update) = rewriter.rewrite(&update.source, &content) else {
+                return Ok(None);
+            };
+            state.history.snapshot(&path, SnapshotReason::Update)?;
+            fs::write_atomic(&path, update.content.as_bytes())?;
+            Ok(Some(update))
+        });
+        match written {
+            Ok(Some(update)) => {
+                state.index.refresh(&path);
+                updated.push(update);
+            },
+            Ok(None) => {},
+            Err(err) => {
+                warn!("Failed to update links in {:?}: {:#}", update.path, err);
+                failed.push(LinkUpdateFailure {
+                    path: update.path.to_string_lossy().into_owned(),
+                    error: err.to_string(),
+                });
+            },
+        }
+    }
+    (updated, failed)
+}
+
+// Helper function to locate a markdown file by its relative path or, failing that, by name.
+// Paths that escape the base directory are rejected with a `VaultPathError`.
+fn find_markdown_file(state: &AppState, filename: &str) -> Result<PathBuf> {
+    let base_dir = &state.base_dir;
+    
+    // First, try the path as given (relative to the base directory)
+    let direct_path = fs::resolve_vault_path(base_dir, filename)?;
+    if direct_path.is_file() {
+        let is_readme = direct_path.file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.to_lowercase().starts_with("readme"))
+            .unwrap_or(false);
+        
+        if is_readme || state.index.is_note_file(&direct_path) {
+            return Ok(direct_path);
+        }
     }
-} 
\ No newline at end of file
+    
+    // Last resort: search for the file by name in all categories
+    let file_name = Path::new(filename).file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(filename);
+    
+    for file in state.index.find_by_name(file_name) {
+        // Skip files that are only reachable through links leaving the base directory
+        if let Ok(path) = fs::resolve_vault_path(base_dir, &file.path.to_string_lossy()) {
+            return Ok(path);
+        }
+    }
+    
+    Err(std::io::Error::new(std::io::ErrorKind::NotFound, "File not found").into())
+}
+
+// Helper function to read an uploaded file, refusing files over the configured size
+async fn read_upload(mut field: Field<'_>, max_file_size_mb: u64) -> Result<Vec<u8>, (StatusCode, String)> {
+    let max_size = max_file_size_mb.saturating_mul(1024 * 1024);
+    let mut data = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+        if (data.len() + chunk.len()) as u64 > max_size {
+            return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("File is larger than {} MB", max_file_size_mb)));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+fn multipart_error(err: MultipartError) -> (StatusCode, String) {
+    (err.status(), err.body_text())
+}
+
+// Helper function to find a markdown file and return its path relative to the base directory
+fn find_note_path(state: &AppState, filename: &str) -> Result<PathBuf> {
+    let path = find_markdown_file(state, filename)?;
+    fs::get_relative_path(&state.base_dir, &path)
+}
+
+// Helper function to check an `If-Match` header, which may list several ETags or `*`
+fn etag_matches(if_match: &HeaderValue, etag: &str) -> bool {
+    let Ok(if_match) = if_match.to_str() else {
+        return false;
+    };
+    
+    // Weak ETags never match for updates
+    if_match.split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate == etag)
+}
+
+// Helper function to build the response for an update that lost a race
+fn conflict_response(status: StatusCode, conflict: SaveConflict) -> Response {
+    (
+        status,
+        Json(ApiConflict {
+            status: "error".to_string(),
+            message: "The note was changed since it was loaded".to_string(),
+            data: conflict,
+        }),
+    ).into_response()
+}
+
+// Helper function to find the relative path a file's history is stored under.
+// Deleted files no longer exist but still have history, in `.mdlib` or git, under their old path.
+fn find_history_path(state: &AppState, filename: &str) -> Result<PathBuf> {
+    match find_note_path(state, filename) {
+        Ok(path) => Ok(path),
+        Err(_) => {
+            let path = fs::resolve_vault_path(&state.base_dir, filename)?;
+            fs::get_relative_path(&state.base_dir, &path)
+        }
+    }
+}
+
+// Helper function to commit changes made through the API when auto-commit is enabled.
+// The change itself has already been made, so a failed commit is only logged.
+fn commit_changes(state: &AppState, paths: &[&Path], message: &str) {
+    let Some(git) = state.git.as_ref().filter(|git| git.auto_commit()) else {
+        return;
+    };
+    
+    if let Err(err) = git.commit(paths, message) {
+        warn!("Failed to commit {:?}: {:#}", paths, err);
+    }
+}
+
+// Helper function to name a note by its path relative to the base directory
+fn note_label(state: &AppState, path: &Path) -> String {
+    fs::get_relative_path(&state.base_dir, path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Helper middleware giving requests rejected by the body limit the same error shape as other API errors
+async fn payload_too_large_response(
+    State(state): State<AppState>,
+    response: Response,
+) -> Response {
+    let is_json = response.headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+    
+    let limit = state.config.read().await.max_file_size_mb;
+    let message = format!("Request is too large; files are limited to {} MB", limit);
+    ApiResult::<()>::Error(StatusCode::PAYLOAD_TOO_LARGE, message).into_response()
+}
+
+// Helper function to pick the HTTP status code that best describes an error
+fn error_status(err: &anyhow::Error) -> StatusCode {
+    if err.downcast_ref::<fs::FileTooLarge>().is_some() {
+        return StatusCode::PAYLOAD_TOO_LARGE;
+    }
+    
+    if err.downcast_ref::<frontmatter::FrontmatterError>().is_some() {
+        return StatusCode::UNPROCESSABLE_ENTITY;
+    }
+    
+    if let Some(err) = err.downcast_ref::<fs::VaultPathError>() {
+        return match err {
+            fs::VaultPathError::Invalid(_) => StatusCode::BAD_REQUEST,
+            fs::VaultPathError::OutsideVault => StatusCode::FORBIDDEN,
+            fs::VaultPathError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+    }
+    
+    if let Some(err) = err.downcast_ref::<std::io::Error>() {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => return StatusCode::NOT_FOUND,
+            std::io::ErrorKind::AlreadyExists | std::io::ErrorKind::DirectoryNotEmpty => return StatusCode::CONFLICT,
+            _ => {},
+        }
+    }
+    
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Stream file change events to the client using Server-Sent Events
+async fn file_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = BroadcastStream::new(state.events.subscribe())
+        // A lagging client just misses some events; it will catch up on the next one
+        .filter_map(|event| event.ok())
+        .map(|event| {
+            let data = serde_json::to_string(&event).unwrap_or_default();
+            Ok(Event::default().event("change").data(data))
+        });
+    
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
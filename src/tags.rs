@@ -0,0 +1,407 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::{parse_document, Arena};
+use serde::Serialize;
+
+use crate::config::TagsConfig;
+use crate::format::NoteFormat;
+use crate::frontmatter::{self, FrontmatterError};
+use crate::fs::MarkdownFile;
+use crate::render;
+
+/// A tag in the tag hierarchy, where `area/infra` is nested under `area`
+#[derive(Debug, Serialize)]
+pub struct TagNode {
+    /// The last part of the tag, e.g. `infra`
+    pub name: String,
+    /// The full tag, e.g. `area/infra`
+    pub tag: String,
+    /// Notes tagged with exactly this tag
+    pub count: usize,
+    /// Notes tagged with this tag or any tag nested under it
+    pub total: usize,
+    pub children: Vec<TagNode>,
+}
+
+/// Renames one or more tags to a new one; renaming several tags to the same
+/// one merges them
+#[derive(Debug)]
+pub struct TagRename {
+    pub from: Vec<String>,
+    pub to: String,
+}
+
+/// A tag renamed in a note
+#[derive(Debug, Serialize)]
+pub struct TagChange {
+    pub from: String,
+    pub to: String,
+    /// Line of the changed hashtag, or none for the frontmatter `tags` list
+    pub line: Option<usize>,
+}
+
+// A hashtag found in the body of a note
+struct Hashtag {
+    tag: String,
+    /// Byte range of the tag (without the `#`) in the body, if it could be located
+    range: Option<Range<usize>>,
+}
+
+// Tag tree under construction, with children keyed by their lowercase name
+#[derive(Default)]
+struct TagTreeBuilder {
+    /// The name as first seen, as tags differing only in case are the same tag
+    name: String,
+    count: usize,
+    total: usize,
+    children: BTreeMap<String, TagTreeBuilder>,
+}
+
+/// Find the `#hashtags` in the body of a note, in order of appearance.
+///
+/// Markdown notes are parsed so that code, links and URLs are skipped, and
+/// in Org mode notes blocks and comments are skipped while headline tags
+/// such as `* Heading :work:urgent:` count too. A tag starts with `#` at the
+/// beginning of a word and runs for as long as there are letters, digits or
+/// configured tag characters; tags made of digits only (such as issue
+/// references like `#12`) don't count.
+pub fn extract_hashtags(content: &str, format: NoteFormat, config: &TagsConfig) -> Vec<String> {
+    let (_, body) = frontmatter::split(content, format);
+    
+    let mut tags: Vec<String> = Vec::new();
+    for hashtag in find_hashtags(body, format, config) {
+        if !tags.contains(&hashtag.tag) {
+            tags.push(hashtag.tag);
+        }
+    }
+    tags
+}
+
+/// Rename tags in a note, both in the frontmatter `tags` list and as
+/// `#hashtags` in the body. Tags nested under a renamed tag move along.
+///
+/// Returns the new content and every change made, or `None` if the note
+/// doesn't use any of the tags.
+pub fn rename_tags(
+    content: &str,
+    format: NoteFormat,
+    rename: &TagRename,
+    config: &TagsConfig,
+) -> Result<Option<(String, Vec<TagChange>)>, FrontmatterError> {
+    let mut changes = Vec::new();
+    
+    let mut properties = frontmatter::parse(content, format)?;
+    let tags = frontmatter::tags(&properties);
+    let mut renamed: Vec<String> = Vec::new();
+    for tag in &tags {
+        let new_tag = match rename.apply(tag) {
+            Some(new_tag) => {
+                changes.push(TagChange { from: tag.clone(), to: new_tag.clone(), line: None });
+                new_tag
+            },
+            None => tag.clone(),
+        };
+        // Merging tags can leave the same tag twice
+        if !renamed.iter().any(|t| t.to_lowercase() == new_tag.to_lowercase()) {
+            renamed.push(new_tag);
+        }
+    }
+    let mut updated = if changes.is_empty() {
+        content.to_string()
+    } else {
+        frontmatter::set_tags(&mut properties, &renamed);
+        frontmatter::replace(content, &properties, format)?
+    };
+    
+    // Replace hashtags from the end so earlier positions stay valid
+    let (_, body) = frontmatter::split(&updated, format);
+    let body_start = updated.len() - body.len();
+    let mut replacements = Vec::new();
+    for hashtag in find_hashtags(body, format, config) {
+        let (Some(range), Some(new_tag)) = (hashtag.range, rename.apply(&hashtag.tag)) else {
+            continue;
+        };
+        let line = body[..range.start].matches('\n').count() + 1 + updated[..body_start].matches('\n').count();
+        changes.push(TagChange { from: hashtag.tag, to: new_tag.clone(), line: Some(line) });
+        replacements.push((body_start + range.start..body_start + range.end, new_tag));
+    }
+    for (range, new_tag) in replacements.into_iter().rev() {
+        updated.replace_range(range, &new_tag);
+    }
+    
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some((updated, changes)))
+}
+
+/// All tags used by the notes, as a tree of nested tags sorted by name.
+/// Tags that only differ in case are counted as one.
+pub fn tag_tree<'a>(notes: impl Iterator<Item = &'a MarkdownFile>) -> Vec<TagNode> {
+    let mut root = TagTreeBuilder::default();
+    
+    for file in notes {
+        // A note counts once towards every tag above its own tags
+        let mut ancestors = BTreeSet::new();
+        for tag in &file.tags {
+            let parts: Vec<&str> = tag.split('/').filter(|part| !part.is_empty()).collect();
+            let mut node = &mut root;
+            for (depth, part) in parts.iter().enumerate() {
+                node = node.children.entry(part.to_lowercase()).or_insert_with(|| TagTreeBuilder {
+                    name: part.to_string(),
+                    ..TagTreeBuilder::default()
+                });
+                if ancestors.insert(parts[..=depth].join("/").to_lowercase()) {
+                    node.total += 1;
+                }
+            }
+            if !parts.is_empty() {
+                node.count += 1;
+            }
+        }
+    }
+    
+    root.into_nodes("")
+}
+
+/// Whether a tag is `ancestor` itself or nested under it, ignoring case
+pub fn is_within(tag: &str, ancestor: &str) -> bool {
+    let tag = tag.to_lowercase();
+    let ancestor = ancestor.trim_end_matches('/').to_lowercase();
+    tag == ancestor || tag.strip_prefix(&ancestor).is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl TagRename {
+    /// The new name of a tag, if the rename applies to it
+    pub fn apply(&self, tag: &str) -> Option<String> {
+        let from = self.from.iter().find(|from| is_within(tag, from))?;
+        // Keep whatever was nested under the renamed tag
+        let depth = from.trim_end_matches('/').split('/').count();
+        let nested: Vec<&str> = tag.split('/').skip(depth).collect();
+        if nested.is_empty() {
+            Some(self.to.clone())
+        } else {
+            Some(format!("{}/{}", self.to, nested.join("/")))
+        }
+    }
+}
+
+impl TagTreeBuilder {
+    fn into_nodes(self, parent: &str) -> Vec<TagNode> {
+        self.children.into_values()
+            .map(|builder| {
+                let tag = if parent.is_empty() { builder.name.clone() } else { format!("{}/{}", parent, builder.name) };
+                TagNode {
+                    name: builder.name.clone(),
+                    count: builder.count,
+                    total: builder.total,
+                    children: builder.into_nodes(&tag),
+                    tag,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Whether a character can be part of a tag
+pub fn is_tag_char(c: char, config: &TagsConfig) -> bool {
+    c.is_alphanumeric() || config.characters.contains(c)
+}
+
+/// Find the hashtags in the body of a note (without frontmatter)
+fn find_hashtags(body: &str, format: NoteFormat, config: &TagsConfig) -> Vec<Hashtag> {
+    if format != NoteFormat::Markdown {
+        return find_text_hashtags(body, format, config);
+    }
+    
+    let mut options = render::options();
+    options.extension.front_matter_delimiter = None;
+    let arena = Arena::new();
+    let root = parse_document(&arena, body, &options);
+    
+    let mut texts = Vec::new();
+    collect_text(root, &mut texts);
+    
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(body.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    
+    let mut hashtags = Vec::new();
+    for node in texts {
+        let ast = node.data.borrow();
+        let NodeValue::Text(literal) = &ast.value else {
+            continue;
+        };
+        
+        // Where the text is in the source; escapes and entities make the text
+        // differ from the source, in which case its tags can't be located
+        let start = line_starts.get(ast.sourcepos.start.line.saturating_sub(1))
+            .map(|line_start| line_start + ast.sourcepos.start.column.saturating_sub(1));
+        let start = start.filter(|&start| body.get(start..).is_some_and(|source| source.starts_with(literal.as_str())));
+        let previous = start.and_then(|start| body[..start].chars().next_back());
+        
+        for (range, tag) in scan_hashtags(literal, previous, config) {
+            hashtags.push(Hashtag {
+                tag,
+                range: start.map(|start| start + range.start..start + range.end),
+            });
+        }
+    }
+    hashtags
+}
+
+/// Find the hashtags in plain text or Org mode, line by line
+fn find_text_hashtags(body: &str, format: NoteFormat, config: &TagsConfig) -> Vec<Hashtag> {
+    let mut hashtags = Vec::new();
+    let mut in_block = false;
+    let mut line_start = 0;
+    for line in body.split_inclusive('\n') {
+        let start = line_start;
+        line_start += line.len();
+        
+        if format == NoteFormat::Org {
+            // Skip source and example blocks
+            let trimmed = line.trim_start();
+            let keyword = trimmed.to_lowercase();
+            if keyword.starts_with("#+begin_") {
+                in_block = true;
+            } else if keyword.starts_with("#+end_") {
+                in_block = false;
+                continue;
+            }
+            if in_block {
+                continue;
+            }
+            
+            // A `#+FILETAGS:` line below the top tags the note like one at the top would
+            if keyword.starts_with("#+filetags:") {
+                let value_start = start + (line.len() - trimmed.len()) + "#+filetags:".len();
+                hashtags.extend(org_filetags(&trimmed["#+filetags:".len()..], config).into_iter().map(|(range, tag)| Hashtag {
+                    tag,
+                    range: Some(value_start + range.start..value_start + range.end),
+                }));
+                continue;
+            }
+            
+            // Other keywords hold no text, and comments are a `#` on its own or
+            // followed by whitespace, unlike hashtags at the start of a line
+            let is_comment = trimmed.strip_prefix('#').is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace));
+            if trimmed.starts_with("#+") || is_comment {
+                continue;
+            }
+            hashtags.extend(org_headline_tags(line, config).into_iter().map(|(range, tag)| Hashtag {
+                tag,
+                range: Some(start + range.start..start + range.end),
+            }));
+        }
+        
+        for (range, tag) in scan_hashtags(line, None, config) {
+            hashtags.push(Hashtag {
+                tag,
+                range: Some(start + range.start..start + range.end),
+            });
+        }
+    }
+    hashtags
+}
+
+/// The tags in the value of an Org mode `#+FILETAGS:` keyword, as in `:work:urgent:`
+fn org_filetags(value: &str, config: &TagsConfig) -> Vec<(Range<usize>, String)> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    for part in value.split([':', ' ', '\t']) {
+        let tag = part.trim_end_matches(['\r', '\n']);
+        if !tag.is_empty() && tag.chars().all(|c| is_tag_char(c, config)) {
+            found.push((offset..offset + tag.len(), tag.to_string()));
+        }
+        offset += part.len() + 1;
+    }
+    found
+}
+
+/// The tags at the end of an Org mode headline, as in `* Heading :work:urgent:`
+fn org_headline_tags(line: &str, config: &TagsConfig) -> Vec<(Range<usize>, String)> {
+    let line = line.trim_end();
+    if !line.starts_with('*') || !line.ends_with(':') {
+        return Vec::new();
+    }
+    let Some(tags_start) = line.rfind(char::is_whitespace).map(|i| i + 1) else {
+        return Vec::new();
+    };
+    let tags = &line[tags_start..];
+    if !tags.starts_with(':') || tags.len() < 3 {
+        return Vec::new();
+    }
+    
+    let mut found = Vec::new();
+    let mut offset = tags_start + 1;
+    for tag in tags[1..tags.len() - 1].split(':') {
+        if !tag.is_empty() && tag.chars().all(|c| is_tag_char(c, config)) && tag.chars().any(char::is_alphabetic) {
+            found.push((offset..offset + tag.len(), tag.to_string()));
+        }
+        offset += tag.len() + 1;
+    }
+    found
+}
+
+/// Gather the text nodes of a document, leaving out code, links, images and
+/// raw HTML
+fn collect_text<'a>(node: &'a AstNode<'a>, texts: &mut Vec<&'a AstNode<'a>>) {
+    match &node.data.borrow().value {
+        NodeValue::Text(_) => {
+            texts.push(node);
+            return;
+        },
+        NodeValue::FrontMatter(_)
+        | NodeValue::CodeBlock(_)
+        | NodeValue::HtmlBlock(_)
+        | NodeValue::Code(_)
+        | NodeValue::HtmlInline(_)
+        | NodeValue::Link(_)
+        | NodeValue::Image(_)
+        | NodeValue::FootnoteReference(_) => return,
+        _ => {},
+    }
+    
+    for child in node.children() {
+        collect_text(child, texts);
+    }
+}
+
+/// Find tags in a piece of text, given the character before it. Returns the
+/// byte range of each tag (without the `#`) along with the tag.
+fn scan_hashtags(text: &str, mut previous: Option<char>, config: &TagsConfig) -> Vec<(Range<usize>, String)> {
+    let mut tags = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    
+    while let Some((i, c)) = chars.next() {
+        // Only at the start of a word, so `C#` and `page#anchor` aren't tags
+        let starts_word = previous.is_none_or(|p: char| p.is_whitespace() || matches!(p, '(' | ','));
+        previous = Some(c);
+        if c != '#' || !starts_word {
+            continue;
+        }
+        
+        let start = i + 1;
+        let mut end = start;
+        while let Some(&(j, c)) = chars.peek() {
+            if !is_tag_char(c, config) {
+                break;
+            }
+            end = j + c.len_utf8();
+            previous = Some(c);
+            chars.next();
+        }
+        
+        // Trailing punctuation belongs to the sentence rather than the tag, and
+        // nested tags are separated by single slashes that can't start a tag
+        let tag = text[start..end].trim_end_matches(|c: char| !c.is_alphanumeric());
+        if tag.starts_with('/') || tag.contains("//") || !tag.chars().any(char::is_alphabetic) {
+            continue;
+        }
+        tags.push((start..start + tag.len(), tag.to_string()));
+    }
+    
+    tags
+}
@@ -0,0 +1,186 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::TrashConfig;
+use crate::fs::{get_relative_path, now_millis, resolve_vault_path, DATA_DIR};
+
+// Metadata stored next to each trashed item
+const INFO_FILE: &str = "info.json";
+
+/// What kind of item was deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashKind {
+    Note,
+    Category,
+}
+
+/// A deleted note or category waiting in the trash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// Identifier used to refer to the entry in the API
+    pub id: String,
+    /// Where the item was, relative to the base directory
+    pub path: PathBuf,
+    /// When the item was deleted, in milliseconds since the Unix epoch
+    // Entries deleted before the API switched to snake_case have `deletedAt`
+    #[serde(alias = "deletedAt")]
+    pub deleted_at: u64,
+    pub kind: TrashKind,
+}
+
+/// Deleted notes and categories.
+///
+/// Every deleted item gets a directory in `.mdlib/trash` inside the notes
+/// directory, holding the item itself and an `info.json` with its original
+/// path and deletion time.
+pub struct Trash {
+    base_dir: PathBuf,
+    dir: PathBuf,
+    config: TrashConfig,
+}
+
+impl Trash {
+    pub fn new(base_dir: &Path, config: TrashConfig) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            dir: base_dir.join(DATA_DIR).join("trash"),
+            config,
+        }
+    }
+    
+    /// Move a note or category into the trash
+    pub fn delete(&self, path: &Path) -> Result<TrashEntry> {
+        let rel_path = get_relative_path(&self.base_dir, path)?;
+        let file_name = path.file_name().context("Cannot delete the notes directory")?;
+        let kind = if path.is_dir() { TrashKind::Category } else { TrashKind::Note };
+        
+        // Entries are named after the time they were deleted; make sure they stay unique
+        let mut deleted_at = now_millis();
+        while self.dir.join(deleted_at.to_string()).exists() {
+            deleted_at += 1;
+        }
+        let entry = TrashEntry {
+            id: deleted_at.to_string(),
+            path: rel_path,
+            deleted_at,
+            kind,
+        };
+        
+        let entry_dir = self.dir.join(&entry.id);
+        fs::create_dir_all(&entry_dir).context("Failed to create trash directory")?;
+        let moved = serde_json::to_string_pretty(&entry)
+            .context("Failed to serialize trash entry")
+            .and_then(|info| fs::write(entry_dir.join(INFO_FILE), info).context("Failed to write trash entry"))
+            .and_then(|_| fs::rename(path, entry_dir.join(file_name)).context("Failed to move item to the trash"));
+        if let Err(err) = moved {
+            // Don't leave an entry behind for an item that is still in place
+            let _ = fs::remove_dir_all(&entry_dir);
+            return Err(err);
+        }
+        
+        self.purge_expired();
+        Ok(entry)
+    }
+    
+    /// All items in the trash, most recently deleted first
+    pub fn entries(&self) -> Result<Vec<TrashEntry>> {
+        let dirs = match fs::read_dir(&self.dir) {
+            Ok(dirs) => dirs,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err).context("Failed to read trash directory"),
+        };
+        
+        let mut entries: Vec<TrashEntry> = dirs
+            .filter_map(|dir| dir.ok())
+            .filter_map(|dir| self.entry(dir.file_name().to_str()?).ok())
+            .collect();
+        
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.deleted_at));
+        Ok(entries)
+    }
+    
+    /// Put an item back where it was, recreating its category if needed.
+    ///
+    /// Returns the restored path relative to the base directory. Fails if
+    /// something else has been created at that path in the meantime.
+    pub fn restore(&self, id: &str) -> Result<PathBuf> {
+        let entry = self.entry(id)?;
+        let target = resolve_vault_path(&self.base_dir, &entry.path.to_string_lossy())?;
+        if target.exists() {
+            let message = format!("{} already exists", entry.path.display());
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, message).into());
+        }
+        
+        let file_name = entry.path.file_name().context("Invalid trash entry")?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).context("Failed to create category directory")?;
+        }
+        let entry_dir = self.dir.join(&entry.id);
+        fs::rename(entry_dir.join(file_name), &target).context("Failed to restore item from the trash")?;
+        fs::remove_dir_all(&entry_dir).context("Failed to remove trash entry")?;
+        
+        Ok(entry.path)
+    }
+    
+    /// Permanently delete an item in the trash
+    pub fn purge(&self, id: &str) -> Result<()> {
+        let entry = self.entry(id)?;
+        fs::remove_dir_all(self.dir.join(&entry.id)).context("Failed to remove trash entry")
+    }
+    
+    /// Permanently delete everything in the trash, returning how many items were removed
+    pub fn purge_all(&self) -> Result<usize> {
+        let entries = self.entries()?;
+        for entry in &entries {
+            self.purge(&entry.id)?;
+        }
+        Ok(entries.len())
+    }
+    
+    /// Permanently delete items that have been in the trash longer than configured
+    pub fn purge_expired(&self) {
+        if self.config.retention_days == 0 {
+            return;
+        }
+        
+        let entries = match self.entries() {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("Failed to read trash: {:#}", err);
+                return;
+            }
+        };
+        
+        let retention = Duration::from_secs(self.config.retention_days * 24 * 60 * 60);
+        let cutoff = now_millis().saturating_sub(retention.as_millis() as u64);
+        for entry in entries.iter().filter(|entry| entry.deleted_at < cutoff) {
+            if let Err(err) = self.purge(&entry.id) {
+                warn!("Failed to purge {:?} from the trash: {:#}", entry.path, err);
+            }
+        }
+    }
+    
+    fn entry(&self, id: &str) -> Result<TrashEntry> {
+        // Ids are timestamps; anything else could point outside the trash
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Trash entry not found").into());
+        }
+        
+        let info = match fs::read_to_string(self.dir.join(id).join(INFO_FILE)) {
+            Ok(info) => info,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "Trash entry not found").into());
+            },
+            Err(err) => return Err(err).context("Failed to read trash entry"),
+        };
+        let mut entry: TrashEntry = serde_json::from_str(&info).context("Failed to parse trash entry")?;
+        entry.id = id.to_string();
+        Ok(entry)
+    }
+}
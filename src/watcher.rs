@@ -0,0 +1,165 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc};
+use anyhow::{Result, Context};
+use tracing::{debug, warn};
+
+use crate::index::NoteIndex;
+use crate::scan;
+
+/// How long to wait for more events before notifying clients
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The kind of change that happened to a file in the notes directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileEventKind {
+    Created,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+/// A change to a file in the notes directory, as pushed to clients
+#[derive(Debug, Clone, Serialize)]
+pub struct FileEvent {
+    pub kind: FileEventKind,
+    /// Paths relative to the base directory. For renames this is `[from, to]`
+    /// when both sides are known.
+    pub paths: Vec<String>,
+}
+
+/// Starts watching the base directory recursively.
+///
+/// Raw filesystem events are debounced, applied to the note index and then
+/// forwarded to `events`. The returned watcher must be kept alive for as long
+/// as events should be delivered.
+pub fn start_watcher(
+    base_dir: &Path,
+    index: Arc<NoteIndex>,
+    events: broadcast::Sender<FileEvent>,
+) -> Result<RecommendedWatcher> {
+    let base_dir = base_dir.canonicalize()
+        .context("Failed to canonicalize notes directory")?;
+    
+    let (raw_tx, raw_rx) = mpsc::unbounded_channel();
+    
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        match res {
+            Ok(event) => {
+                // The receiver only goes away when the server shuts down
+                let _ = raw_tx.send(event);
+            },
+            Err(err) => warn!("File watcher error: {}", err),
+        }
+    }).context("Failed to create file watcher")?;
+    
+    watcher.watch(&base_dir, RecursiveMode::Recursive)
+        .context("Failed to watch notes directory")?;
+    
+    tokio::spawn(forward_events(base_dir, raw_rx, index, events));
+    
+    Ok(watcher)
+}
+
+/// Collects raw events into batches and broadcasts one `FileEvent` per change
+async fn forward_events(
+    base_dir: PathBuf,
+    mut raw_rx: mpsc::UnboundedReceiver<notify::Event>,
+    index: Arc<NoteIndex>,
+    events: broadcast::Sender<FileEvent>,
+) {
+    while let Some(first) = raw_rx.recv().await {
+        let mut batch = vec![first];
+        
+        // Editors tend to emit several events per save, so wait for things to settle
+        while let Ok(Some(event)) = tokio::time::timeout(DEBOUNCE_INTERVAL, raw_rx.recv()).await {
+            batch.push(event);
+        }
+        
+        // Changed ignore files have to be read again before deciding what else is relevant
+        for path in batch.iter().flat_map(|event| &event.paths) {
+            if path.file_name().is_some_and(scan::is_ignore_file) {
+                if let Some(dir) = path.parent() {
+                    index.forget_ignore_rules(dir);
+                }
+            }
+        }
+        
+        let mut changes = BTreeSet::new();
+        for event in batch {
+            if let Some(change) = convert_event(&base_dir, &index, &event) {
+                changes.insert((change.kind, change.paths));
+            }
+        }
+        
+        // Update the index first so clients reacting to the event see fresh data
+        let changed_paths: BTreeSet<String> = changes.iter()
+            .flat_map(|(_, paths)| paths.iter().cloned())
+            .collect();
+        let sync_index = index.clone();
+        let synced = tokio::task::spawn_blocking(move || {
+            for path in changed_paths {
+                sync_index.refresh(Path::new(&path));
+            }
+        }).await;
+        if let Err(err) = synced {
+            warn!("Failed to update note index: {}", err);
+        }
+        
+        for (kind, paths) in changes {
+            debug!("File {:?}: {:?}", kind, paths);
+            // Sending only fails when no client is listening, which is fine
+            let _ = events.send(FileEvent { kind, paths });
+        }
+    }
+}
+
+/// Converts a raw notify event into a `FileEvent`, skipping irrelevant files
+fn convert_event(base_dir: &Path, index: &NoteIndex, event: &notify::Event) -> Option<FileEvent> {
+    let kind = match event.kind {
+        EventKind::Create(_) => FileEventKind::Created,
+        EventKind::Modify(ModifyKind::Name(_)) => FileEventKind::Renamed,
+        EventKind::Modify(ModifyKind::Metadata(_)) => return None,
+        EventKind::Modify(_) => FileEventKind::Modified,
+        EventKind::Remove(_) => FileEventKind::Deleted,
+        _ => return None,
+    };
+    
+    let paths: Vec<String> = event.paths.iter()
+        .filter_map(|path| path.strip_prefix(base_dir).ok())
+        .filter(|path| is_relevant_path(base_dir, index, path))
+        .map(|path| path.to_string_lossy().replace('\\', "/"))
+        .filter(|path| !path.is_empty())
+        .collect();
+    
+    if paths.is_empty() {
+        return None;
+    }
+    
+    Some(FileEvent { kind, paths })
+}
+
+/// Whether a relative path is a note or a (possible) category directory
+/// worth reporting, or an ignore file whose changes affect which notes are listed
+fn is_relevant_path(base_dir: &Path, index: &NoteIndex, path: &Path) -> bool {
+    if path.file_name().is_some_and(scan::is_ignore_file) {
+        return path.parent().is_none_or(|dir| !index.is_ignored(dir, true));
+    }
+    
+    // Skip ignored files and directories such as .git or node_modules
+    if index.is_ignored(path, base_dir.join(path).is_dir()) {
+        return false;
+    }
+    
+    match path.extension() {
+        Some(_) => index.is_note_file(path),
+        // Directories (categories) and extension-less README files
+        None => true,
+    }
+}
@@ -49,9 +49,14 @@ document.addEventListener('DOMContentLoaded', () => {
     let viewMode = 'preview'; // 'preview', 'edit', or 'split'
     let isDarkMode = localStorage.getItem('darkMode') === 'true';
     let autoSaveTimeout = null;
+    let pendingSave = Promise.resolve();
+    let currentEtag = null;
+    let savedContent = null;
     let allTags = new Set();
     let categories = [];
     let isMobile = window.innerWidth < 768;
+    let previewRequest = 0;
+    let noteExtensions = ['md', 'markdown', 'mdx']; // Replaced by the configured ones on load
     
     // Initialize the application
     init();
@@ -67,8 +72,8 @@ document.addEventListener('DOMContentLoaded', () => {
             console.error('highlight.js is not loaded. Syntax highlighting will not work.');
         }
         
-        // Load files
-        loadFiles();
+        // Load files once the note extensions are known
+        loadConfig().then(loadFiles);
         
         // Load categories
         loadCategories();
@@ -84,39 +89,21 @@ document.addEventListener('DOMContentLoaded', () => {
             document.body.classList.add('dark-mode');
         }
         
-        // Configure marked renderer
-        configureMarked();
-        
         // Check screen size and set up responsive behavior
         checkScreenSize();
         window.addEventListener('resize', debounce(checkScreenSize, 100));
         
         // Create toast container
         createToastContainer();
-    }
-
-    // Configure marked for rendering markdown
-    function configureMarked() {
-        marked.setOptions({
-            breaks: true,
-            gfm: true,
-            headerIds: true,
-            highlight: function(code, lang) {
-                if (typeof hljs === 'undefined') {
-                    return code; // Fallback if highlight.js isn't loaded
-                }
-                
-                try {
-                    if (lang && hljs.getLanguage(lang)) {
-                        return hljs.highlight(lang, code).value;
-                    }
-                    return hljs.highlightAuto(code).value;
-                } catch (e) {
-                    console.error('Error highlighting code:', e);
-                    return code; // Return original code on error
-                }
-            }
-        });
+        
+        // Listen for changes made outside the app
+        subscribeToFileEvents();
+        
+        // Open the note from the URL, e.g. when following a rendered wiki link
+        const noteFromUrl = new URLSearchParams(window.location.search).get('note');
+        if (noteFromUrl) {
+            loadFile(noteFromUrl);
+        }
     }
 
     // Set up event listeners
@@ -144,7 +131,7 @@ document.addEventListener('DOMContentLoaded', () => {
         });
         
         // Save the current file
-        btnSave.addEventListener('click', saveCurrentFile);
+        btnSave.addEventListener('click', () => saveCurrentFile());
         
         // Create a new note from the modal
         btnModalCreate.addEventListener('click', createNewNote);
@@ -180,11 +167,33 @@ document.addEventListener('DOMContentLoaded', () => {
         });
         
         // Auto-preview as you type
+        const debouncedPreview = debounce(updatePreview, 150);
         editor.addEventListener('input', () => {
-            updatePreview();
+            debouncedPreview();
             scheduleAutoSave();
         });
         
+        // Upload files pasted or dropped into the editor, such as screenshots
+        editor.addEventListener('paste', e => {
+            const files = Array.from(e.clipboardData ? e.clipboardData.files : []);
+            if (files.length > 0) {
+                e.preventDefault();
+                files.forEach(uploadAttachment);
+            }
+        });
+        editor.addEventListener('dragover', e => {
+            if (e.dataTransfer && e.dataTransfer.types.includes('Files')) {
+                e.preventDefault();
+            }
+        });
+        editor.addEventListener('drop', e => {
+            const files = Array.from(e.dataTransfer ? e.dataTransfer.files : []);
+            if (files.length > 0) {
+                e.preventDefault();
+                files.forEach(uploadAttachment);
+            }
+        });
+        
         // Format text using toolbar buttons
         toolbarButtons.forEach(button => {
             button.addEventListener('click', () => {
@@ -197,11 +206,20 @@ document.addEventListener('DOMContentLoaded', () => {
         preview.addEventListener('click', e => {
             // Check if the clicked element is a link
             if (e.target.tagName === 'A') {
-                e.preventDefault();
                 const href = e.target.getAttribute('href');
                 
-                // Only handle .md links or links without extension (assumed to be markdown)
-                if (href && (href.endsWith('.md') || !href.includes('.'))) {
+                // Let heading anchors and footnotes scroll within the preview
+                if (href && href.startsWith('#')) {
+                    return;
+                }
+                e.preventDefault();
+                
+                // Wiki links rendered by the server point at /?note=<path>
+                if (href && href.startsWith('/?note=')) {
+                    const url = new URL(href, window.location.origin);
+                    loadFile(url.searchParams.get('note'));
+                } else if (href && (hasNoteExtension(href) || !href.includes('.'))) {
+                    // Only handle links to notes or links without extension (assumed to be markdown)
                     // Remove leading slash if present for consistency
                     const cleanHref = href.startsWith('/') ? href.substring(1) : href;
                     console.log('Loading markdown link:', cleanHref);
@@ -275,6 +293,77 @@ document.addEventListener('DOMContentLoaded', () => {
         document.body.appendChild(container);
     }
 
+    // Subscribe to file change events pushed by the server
+    function subscribeToFileEvents() {
+        if (typeof EventSource === 'undefined') {
+            return;
+        }
+        
+        const events = new EventSource('/api/events');
+        const refreshLists = debounce(() => {
+            loadFiles();
+            loadCategories();
+        }, 300);
+        
+        events.addEventListener('change', e => {
+            let change;
+            try {
+                change = JSON.parse(e.data);
+            } catch (error) {
+                console.error('Error parsing file event:', error);
+                return;
+            }
+            
+            refreshLists();
+            
+            if (currentFile && change.paths.some(path => isSamePath(currentFile, path))) {
+                handleCurrentFileChange(change);
+            }
+        });
+    }
+    
+    // React to a change of the currently open note
+    function handleCurrentFileChange(change) {
+        switch (change.kind) {
+            case 'deleted':
+                showToast('This note was deleted outside mdlib', 'error');
+                break;
+            case 'renamed':
+                if (change.paths.length === 2 && isSamePath(currentFile, change.paths[0])) {
+                    currentFile = currentFile.slice(0, currentFile.length - change.paths[0].length) + change.paths[1];
+                    currentFilename.textContent = getFilename(currentFile);
+                }
+                break;
+            default:
+                checkCurrentFileChanged();
+        }
+    }
+    
+    // Reload the open note if it differs from what was last loaded or saved.
+    // Our own saves come back as changes too, with the ETag the save returned.
+    function checkCurrentFileChanged() {
+        const path = currentFile;
+        pendingSave
+            .then(() => fetch(`/api/files/${encodeURIComponent(path)}`, { method: 'HEAD' }))
+            .then(response => {
+                if (path !== currentFile || !response.ok || response.headers.get('ETag') === currentEtag) {
+                    return;
+                }
+                if (isEditing) {
+                    // Don't throw away local edits
+                    showToast('This note was changed outside mdlib', 'error');
+                } else {
+                    loadFile(currentFile);
+                }
+            })
+            .catch(error => console.error('Error checking the note for changes:', error));
+    }
+    
+    // Check whether a file path refers to a path relative to the notes directory
+    function isSamePath(filePath, relativePath) {
+        return filePath === relativePath || filePath.endsWith(`/${relativePath}`);
+    }
+
     // Show new note modal
     function showNewNoteModal() {
         // Load categories first to ensure dropdown is populated
@@ -331,6 +420,21 @@ document.addEventListener('DOMContentLoaded', () => {
         }
     }
 
+    // Load the settings the server shares with the browser, keeping the
+    // defaults if that fails
+    function loadConfig() {
+        return fetch('/api/config')
+            .then(response => response.json())
+            .then(data => {
+                if (data.status === 'success') {
+                    noteExtensions = data.data.extensions;
+                } else {
+                    console.error('Error loading config:', data.message);
+                }
+            })
+            .catch(error => console.error('Error loading config:', error));
+    }
+
     // Load all markdown files
     function loadFiles() {
         fileList.innerHTML = `
@@ -378,32 +482,32 @@ document.addEventListener('DOMContentLoaded', () => {
         // Process and sort files
         const processedFiles = files.map(file => {
             // Extract just the file name without path and extension
-            let fileName = file.path.split('/').pop().replace('.md', '');
-            
-            // Extract category if it exists in the path
-            let category = '';
-            const pathParts = file.path.split('/');
-            if (pathParts.length > 2) {
-                category = pathParts[pathParts.length - 2];
-            }
+            let fileName = stripNoteExtension(file.path.split('/').pop());
             
             return {
                 path: file.path,
                 name: fileName,
-                category: category,
-                tags: file.tags || []
+                category: file.category || '',
+                tags: file.tags || [],
+                snippet: file.snippet || '',
+                tooLarge: !!file.too_large
             };
         });
         
-        // Sort files alphabetically by name and secondarily by category
-        processedFiles.sort((a, b) => {
-            // First sort by category if it exists
-            if (a.category && b.category && a.category !== b.category) {
-                return a.category.localeCompare(b.category);
-            }
-            // Then sort by name
-            return a.name.localeCompare(b.name);
-        });
+        // Search results arrive ranked by relevance, so keep their order
+        const isRanked = files.some(file => typeof file.score === 'number');
+        
+        if (!isRanked) {
+            // Sort files alphabetically by name and secondarily by category
+            processedFiles.sort((a, b) => {
+                // First sort by category if it exists
+                if (a.category && b.category && a.category !== b.category) {
+                    return a.category.localeCompare(b.category);
+                }
+                // Then sort by name
+                return a.name.localeCompare(b.name);
+            });
+        }
         
         // Create a document fragment for better performance
         const fragment = document.createDocumentFragment();
@@ -414,7 +518,7 @@ document.addEventListener('DOMContentLoaded', () => {
         // Add each file to the list
         processedFiles.forEach(file => {
             // If category is changing, add a visual separator
-            if (file.category && file.category !== lastCategory) {
+            if (!isRanked && file.category && file.category !== lastCategory) {
                 lastCategory = file.category;
                 
                 // Only add a separator if this isn't the first category
@@ -454,6 +558,23 @@ document.addEventListener('DOMContentLoaded', () => {
                 noteTitle.appendChild(tagsIndicator);
             }
             
+            // Files over the size limit are listed but can't be opened
+            if (file.tooLarge) {
+                const sizeIndicator = document.createElement('span');
+                sizeIndicator.className = 'ml-1 text-xs text-red-500 flex-shrink-0';
+                sizeIndicator.textContent = '(too large)';
+                sizeIndicator.title = 'This file is bigger than the configured maximum file size';
+                noteTitle.appendChild(sizeIndicator);
+            }
+            
+            // Show where a search matched
+            if (file.snippet) {
+                const snippet = document.createElement('span');
+                snippet.className = 'search-snippet';
+                snippet.innerHTML = DOMPurify.sanitize(file.snippet, { ALLOWED_TAGS: ['mark'] });
+                noteTitle.appendChild(snippet);
+            }
+            
             // Add delete button
             const deleteBtn = document.createElement('button');
             deleteBtn.className = 'delete-btn ml-2 opacity-0 group-hover:opacity-100 text-gray-400 hover:text-red-500 transition-opacity flex-shrink-0';
@@ -589,7 +710,7 @@ document.addEventListener('DOMContentLoaded', () => {
             Rename
         `;
         renameOption.addEventListener('click', () => {
-            const newName = prompt('Enter new name:', getFilename(filePath).replace('.md', ''));
+            const newName = prompt('Enter new name:', stripNoteExtension(getFilename(filePath)));
             if (newName) {
                 // TODO: Implement rename functionality
                 closeContextMenu();
@@ -620,12 +741,10 @@ document.addEventListener('DOMContentLoaded', () => {
             Change Category
         `;
         changeCategoryOption.addEventListener('click', () => {
-            // Get the current category
-            const pathParts = filePath.split('/');
-            let currentCategory = '';
-            if (pathParts.length > 2) {
-                currentCategory = pathParts[pathParts.length - 2];
-            }
+            // Get the current category (paths are relative to the notes directory)
+            const currentCategory = filePath.includes('/')
+                ? filePath.substring(0, filePath.lastIndexOf('/'))
+                : '';
             
             // Create a dropdown with available categories
             let categoryOptions = '<option value="">No Category</option>';
@@ -715,9 +834,16 @@ document.addEventListener('DOMContentLoaded', () => {
     function loadFile(path) {
         // Need to use the full path for files in categories
         fetch(`/api/files/${encodeURIComponent(path)}`)
-            .then(response => response.json())
-            .then(data => {
+            .then(response => {
+                const etag = response.headers.get('ETag');
+                return response.json().then(data => ({ data, etag }));
+            })
+            .then(({ data, etag }) => {
                 if (data.status === 'success') {
+                    // Remember what we loaded so saves can detect changes made elsewhere
+                    currentEtag = etag;
+                    savedContent = data.data;
+                    
                     // Update the editor and preview
                     editor.value = data.data;
                     updatePreview();
@@ -757,17 +883,34 @@ document.addEventListener('DOMContentLoaded', () => {
             });
     }
 
-    // Update the preview with the current editor content
+    // Update the preview with the current editor content, rendered by the server
     function updatePreview() {
         if (!editor.value) {
             preview.innerHTML = '<div class="text-gray-400 italic">Nothing to preview</div>';
             return;
         }
         
-        try {
-            // Sanitize HTML to prevent XSS
-            const sanitizedHtml = DOMPurify.sanitize(marked.parse(editor.value));
-            preview.innerHTML = sanitizedHtml;
+        // Ignore responses that arrive after a newer render was requested
+        const request = ++previewRequest;
+        
+        fetch('/api/render', {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json'
+            },
+            body: JSON.stringify({ content: editor.value, path: currentFile })
+        })
+        .then(response => response.json())
+        .then(data => {
+            if (request !== previewRequest) {
+                return;
+            }
+            if (data.status !== 'success') {
+                throw new Error(data.message);
+            }
+            
+            // The server already sanitizes, but keep a second line of defense
+            preview.innerHTML = DOMPurify.sanitize(data.data.html);
             
             // Apply syntax highlighting to code blocks
             if (typeof hljs !== 'undefined') {
@@ -775,32 +918,49 @@ document.addEventListener('DOMContentLoaded', () => {
                     hljs.highlightBlock(block);
                 });
             }
-        } catch (error) {
+        })
+        .catch(error => {
             console.error('Error rendering markdown:', error);
-            preview.innerHTML = `<div class="text-red-500">Error rendering markdown: ${error.message}</div>`;
-        }
+            const message = document.createElement('div');
+            message.className = 'text-red-500';
+            message.textContent = `Error rendering markdown: ${error.message}`;
+            preview.innerHTML = '';
+            preview.appendChild(message);
+        });
     }
 
-    // Save the current file
-    function saveCurrentFile() {
+
+    // Save the current file. Autosaves never prompt the user.
+    function saveCurrentFile(isAutoSave = false) {
         if (!currentFile) {
             alert('No file is currently open.');
             return;
         }
         
         const content = editor.value;
+        const headers = {
+            'Content-Type': 'application/json'
+        };
+        // Only save if nobody else changed the note since we loaded it
+        if (currentEtag) {
+            headers['If-Match'] = currentEtag;
+        }
         
         // Use the full path stored in currentFile
-        fetch(`/api/files/${encodeURIComponent(currentFile)}`, {
+        pendingSave = fetch(`/api/files/${encodeURIComponent(currentFile)}`, {
             method: 'PUT',
-            headers: {
-                'Content-Type': 'application/json'
-            },
-            body: JSON.stringify({ content })
+            headers,
+            body: JSON.stringify({ content, base: savedContent })
         })
-        .then(response => response.json())
-        .then(data => {
+        .then(response => {
+            const etag = response.headers.get('ETag');
+            return response.json().then(data => ({ data, etag }));
+        })
+        .then(({ data, etag }) => {
             if (data.status === 'success') {
+                currentEtag = etag;
+                savedContent = content;
+                
                 // Show success message
                 const saveBtn = document.getElementById('btn-save');
                 const originalText = saveBtn.innerHTML;
@@ -818,6 +978,8 @@ document.addEventListener('DOMContentLoaded', () => {
                 
                 // Update preview
                 updatePreview();
+            } else if (data.data && data.data.etag) {
+                handleSaveConflict(content, data.data, isAutoSave);
             } else {
                 console.error('Error saving file:', data.message);
                 alert(`Error saving file: ${data.message}`);
@@ -828,6 +990,34 @@ document.addEventListener('DOMContentLoaded', () => {
             alert('Error saving file. Please try again.');
         });
     }
+    
+    // Deal with a save rejected because the note changed elsewhere
+    function handleSaveConflict(content, conflict, isAutoSave) {
+        if (conflict.merged !== undefined) {
+            // Kept typing in the meantime: the next save will merge again
+            if (editor.value !== content) {
+                return;
+            }
+            editor.value = conflict.merged;
+            currentEtag = conflict.etag;
+            savedContent = conflict.content;
+            updatePreview();
+            showToast('Merged changes made elsewhere into this note', 'success');
+            saveCurrentFile(isAutoSave);
+            return;
+        }
+        
+        showToast('This note was changed elsewhere and your edits overlap', 'error');
+        if (isAutoSave) {
+            return;
+        }
+        
+        if (confirm('This note was changed elsewhere and your edits overlap with those changes.\n\nOverwrite the other changes with your version? Cancel keeps your version in the editor without saving.')) {
+            currentEtag = conflict.etag;
+            savedContent = conflict.content;
+            saveCurrentFile();
+        }
+    }
 
     // Schedule auto-save
     function scheduleAutoSave() {
@@ -837,7 +1027,7 @@ document.addEventListener('DOMContentLoaded', () => {
         
         autoSaveTimeout = setTimeout(() => {
             if (currentFile && isEditing) {
-                saveCurrentFile();
+                saveCurrentFile(true);
             }
         }, 5000); // Auto-save after 5 seconds of inactivity
     }
@@ -851,30 +1041,52 @@ document.addEventListener('DOMContentLoaded', () => {
             return;
         }
         
-        // Add .md extension if not present
-        const fileName = name.endsWith('.md') ? name : `${name}.md`;
+        // Without a note extension the server adds the default one
+        let fileName = name;
+        const title = stripNoteExtension(name);
+        const extension = hasNoteExtension(name) ? name.split('.').pop().toLowerCase() : '';
         
         // Get category and tags
         const category = newNoteCategory.value.trim();
         const tagsInput = newNoteTags.value.trim();
         const tags = tagsInput ? tagsInput.split(',').map(tag => tag.trim()).filter(tag => tag) : [];
         
-        // Generate frontmatter if we have category or tags
-        let frontmatter = '';
-        if (category || tags.length > 0) {
-            frontmatter = '---\n';
+        let content;
+        if (extension === 'org') {
+            // Org mode keeps its properties in #+KEY: lines
+            let keywords = `#+TITLE: ${title}\n`;
             if (category) {
-                frontmatter += `category: ${category}\n`;
+                keywords += `#+CATEGORY: ${category}\n`;
             }
             if (tags.length > 0) {
-                frontmatter += `tags: [${tags.join(', ')}]\n`;
+                keywords += `#+FILETAGS: :${tags.join(':')}:\n`;
             }
-            frontmatter += '---\n\n';
+            content = keywords + '\n* ' + title + '\n\nStart writing here...';
+        } else if (extension === 'txt') {
+            // Plain text has no properties, so the category is the folder and tags are hashtags
+            if (category) {
+                fileName = `${category}/${fileName}`;
+            }
+            const hashtags = tags.length > 0 ? '\n\n' + tags.map(tag => `#${tag}`).join(' ') : '';
+            content = title + hashtags + '\n\nStart writing here...';
+        } else {
+            // Generate frontmatter if we have category or tags
+            let frontmatter = '';
+            if (category || tags.length > 0) {
+                frontmatter = '---\n';
+                if (category) {
+                    frontmatter += `category: ${category}\n`;
+                }
+                if (tags.length > 0) {
+                    frontmatter += `tags: [${tags.join(', ')}]\n`;
+                }
+                frontmatter += '---\n\n';
+            }
+            
+            // Create content with frontmatter
+            content = frontmatter + '# ' + title + '\n\nStart writing your markdown here...';
         }
         
-        // Create content with frontmatter
-        const content = frontmatter + '# ' + name + '\n\nStart writing your markdown here...';
-        
         // Create the file
         fetch('/api/files', {
             method: 'POST',
@@ -928,8 +1140,12 @@ document.addEventListener('DOMContentLoaded', () => {
                 if (data.status === 'success') {
                     displayFiles(data.data);
                 } else {
-                    fileList.innerHTML = '<li class="text-red-500 text-sm italic">Error searching files</li>';
-                    console.error('Error searching files:', data.message);
+                    // Usually a query syntax error, e.g. while still typing
+                    const item = document.createElement('li');
+                    item.className = 'text-red-500 text-sm italic';
+                    item.textContent = data.message || 'Error searching files';
+                    fileList.innerHTML = '';
+                    fileList.appendChild(item);
                 }
             })
             .catch(error => {
@@ -986,6 +1202,42 @@ document.addEventListener('DOMContentLoaded', () => {
         scheduleAutoSave();
     }
 
+    // Upload a file next to the current note and insert a link to it at the cursor
+    function uploadAttachment(file) {
+        if (!currentFile || !isEditing) {
+            showToast('Open a note in the editor to attach files', 'error');
+            return;
+        }
+        
+        const formData = new FormData();
+        formData.append('note', currentFile);
+        formData.append('file', file, file.name);
+        
+        fetch('/api/attachments', {
+            method: 'POST',
+            body: formData
+        })
+        .then(response => response.json())
+        .then(data => {
+            if (data.status === 'success') {
+                const start = editor.selectionStart;
+                const end = editor.selectionEnd;
+                editor.setRangeText(data.data.markdown, start, end, 'end');
+                editor.focus();
+                
+                updatePreview();
+                scheduleAutoSave();
+                showToast(`Attached ${file.name}`, 'success');
+            } else {
+                showToast(`Error uploading ${file.name}: ${data.message}`, 'error');
+            }
+        })
+        .catch(error => {
+            console.error('Error uploading attachment:', error);
+            showToast(`Error uploading ${file.name}. Please try again.`, 'error');
+        });
+    }
+
     // Handle keyboard shortcuts
     function handleKeyboardShortcuts(e) {
         // Only process if we're in edit mode
@@ -1024,6 +1276,17 @@ document.addEventListener('DOMContentLoaded', () => {
         return path.split('/').pop();
     }
 
+    // Whether a file name or link ends with one of the configured note extensions
+    function hasNoteExtension(name) {
+        const dot = name.lastIndexOf('.');
+        return dot > 0 && noteExtensions.includes(name.slice(dot + 1).toLowerCase());
+    }
+
+    // Remove the note extension from a file name
+    function stripNoteExtension(name) {
+        return hasNoteExtension(name) ? name.slice(0, name.lastIndexOf('.')) : name;
+    }
+
     // Debounce function to limit how often a function is called
     function debounce(func, delay) {
         let timeout;
@@ -1169,11 +1432,16 @@ document.addEventListener('DOMContentLoaded', () => {
             listItem.style.justifyContent = 'flex-start';
             listItem.style.textAlign = 'left';
             
+            // Nested categories are indented under their parent and show their own name
+            const parts = category.split('/');
+            listItem.style.paddingLeft = `${0.75 + (parts.length - 1) * 1}rem`;
+            const displayName = parts[parts.length - 1];
+            
             listItem.innerHTML = `
                 <svg xmlns="http://www.w3.org/2000/svg" class="h-4 w-4 text-gray-500 flex-shrink-0" fill="none" viewBox="0 0 24 24" stroke="currentColor">
                     <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M3 7v10a2 2 0 002 2h14a2 2 0 002-2V9a2 2 0 00-2-2h-6l-2-2H5a2 2 0 00-2 2z" />
                 </svg>
-                <span class="category-name text-left" style="min-width: 0; flex: 1;" title="${category}">${category}</span>
+                <span class="category-name text-left" style="min-width: 0; flex: 1;" title="${category}">${displayName}</span>
                 <button class="category-delete-btn flex-shrink-0">
                     <svg xmlns="http://www.w3.org/2000/svg" class="h-4 w-4 text-gray-400 hover:text-red-500" fill="none" viewBox="0 0 24 24" stroke="currentColor">
                         <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M19 7l-.867 12.142A2 2 0 0116.138 21H7.862a2 2 0 01-1.995-1.858L5 7m5 4v6m4-6v6m1-10V4a1 1 0 00-1-1h-4a1 1 0 00-1 1v3M4 7h16" />
@@ -1275,19 +1543,20 @@ document.addEventListener('DOMContentLoaded', () => {
     
     // Update tags display
     function updateTagsDisplay() {
-        // Collect all tags from files
+        // Collect all tags, parents before the tags nested under them
         allTags = new Set();
+        const tagCounts = new Map();
         
-        fetch('/api/files')
+        fetch('/api/tags')
             .then(response => response.json())
             .then(data => {
                 if (data.status === 'success') {
-                    // Process all files and collect tags
-                    data.data.forEach(file => {
-                        if (file.tags && Array.isArray(file.tags)) {
-                            file.tags.forEach(tag => allTags.add(tag));
-                        }
+                    const collect = nodes => nodes.forEach(node => {
+                        allTags.add(node.tag);
+                        tagCounts.set(node.tag, node.total);
+                        collect(node.children);
                     });
+                    collect(data.data);
                     
                     // Display the collected tags
                     if (allTags.size === 0) {
@@ -1298,7 +1567,7 @@ document.addEventListener('DOMContentLoaded', () => {
                     tagsContainer.innerHTML = '';
                     
                     // Add each tag
-                    Array.from(allTags).sort().forEach(tag => {
+                    allTags.forEach(tag => {
                         const tagSpan = document.createElement('span');
                         tagSpan.className = 'tag text-xs bg-indigo-100 text-indigo-800 rounded px-2 py-1 cursor-pointer hover:bg-indigo-200 flex items-center gap-1';
                         
@@ -1307,6 +1576,12 @@ document.addEventListener('DOMContentLoaded', () => {
                         tagText.textContent = tag;
                         tagSpan.appendChild(tagText);
                         
+                        // Number of notes with the tag or a tag nested under it
+                        const tagCount = document.createElement('span');
+                        tagCount.className = 'opacity-60';
+                        tagCount.textContent = tagCounts.get(tag);
+                        tagSpan.appendChild(tagCount);
+                        
                         tagSpan.addEventListener('click', () => {
                             searchByTag(tag);
                         });
@@ -1323,7 +1598,8 @@ document.addEventListener('DOMContentLoaded', () => {
     
     // Search by category
     function searchByCategory(category) {
-        fetch(`/api/search?category=${encodeURIComponent(category)}`)
+        // Include notes in nested categories
+        fetch(`/api/search?category=${encodeURIComponent(category)}&subcategories=true`)
             .then(response => response.json())
             .then(data => {
                 if (data.status === 'success') {
@@ -1341,7 +1617,8 @@ document.addEventListener('DOMContentLoaded', () => {
     
     // Search by tag
     function searchByTag(tag) {
-        fetch(`/api/search?tag=${encodeURIComponent(tag)}`)
+        // Include notes with tags nested under this one
+        fetch(`/api/search?tag=${encodeURIComponent(tag)}&subtags=true`)
             .then(response => response.json())
             .then(data => {
                 if (data.status === 'success') {
@@ -1404,7 +1681,7 @@ document.addEventListener('DOMContentLoaded', () => {
                 loadFiles();
                 
                 // Show success message
-                showToast('Note deleted successfully', 'success');
+                showToast('Note moved to the trash', 'success');
             } else {
                 console.error('Error deleting file:', data.message);
                 showToast(`Error deleting note: ${data.message}`, 'error');
@@ -1453,24 +1730,30 @@ document.addEventListener('DOMContentLoaded', () => {
     }
 
     // Delete a category
-    function deleteCategory(category) {
-        if (!confirm(`Are you sure you want to delete the category "${category}"?`)) {
+    function deleteCategory(category, recursive = false) {
+        if (!recursive && !confirm(`Are you sure you want to delete the category "${category}"?`)) {
             return;
         }
         
-        fetch(`/api/category/${encodeURIComponent(category)}`, {
+        const query = recursive ? '?recursive=true' : '';
+        fetch(`/api/category/${encodeURIComponent(category)}${query}`, {
             method: 'DELETE'
         })
-        .then(response => response.json())
-        .then(data => {
-            if (data.status === 'success') {
+        .then(response => response.json().then(data => ({ conflict: response.status === 409, data })))
+        .then(({ conflict, data }) => {
+            if (conflict && !recursive) {
+                // The category still has notes or subcategories
+                if (confirm(`The category "${category}" is not empty. Move it to the trash with everything in it?`)) {
+                    deleteCategory(category, true);
+                }
+            } else if (data.status === 'success') {
                 // Refresh categories
                 loadCategories();
                 // Refresh files
                 loadFiles();
                 
                 // Show success message
-                showToast(`Category "${category}" deleted successfully`, 'success');
+                showToast(`Category "${category}" moved to the trash`, 'success');
             } else {
                 console.error('Error deleting category:', data.message);
                 showToast(`Error: ${data.message}`, 'error');
@@ -1494,7 +1777,7 @@ document.addEventListener('DOMContentLoaded', () => {
             },
             body: JSON.stringify({
                 path: filePath,
-                newPath: newCategory ? `/${newCategory}/${fileName}` : `/${fileName}`
+                new_path: newCategory ? `/${newCategory}/${fileName}` : `/${fileName}`
             })
         })
         .then(response => response.json())
@@ -1502,14 +1785,22 @@ document.addEventListener('DOMContentLoaded', () => {
             if (data.status === 'success') {
                 // If the current file is the one being moved, update currentFile
                 if (currentFile === filePath) {
-                    currentFile = data.newPath;
+                    currentFile = data.data.path;
                 }
                 
                 // Refresh the file list
                 loadFiles();
                 
-                // Show success message
-                showToast('Note category changed successfully', 'success');
+                // Show success message, mentioning any notes whose links were rewritten
+                const updated = data.data.updated_links.length;
+                const linkMessage = updated ? ` (updated links in ${updated} note${updated === 1 ? '' : 's'})` : '';
+                const failed = data.data.failed_links;
+                if (failed.length) {
+                    const names = failed.map(failure => failure.path).join(', ');
+                    showToast(`Note category changed, but failed to update links in ${names}`, 'error');
+                } else {
+                    showToast(`Note category changed successfully${linkMessage}`, 'success');
+                }
             } else {
                 console.error('Error changing category:', data.message);
                 showToast(`Error: ${data.message}`, 'error');